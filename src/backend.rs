@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use futures_timer::Delay;
+
+/// Target of register reads and writes.
+///
+/// Implementations are allowed to block, every operation is executed on its own
+/// thread by [`read`] and [`write`] so the GUI stays responsive.
+pub trait Backend: Send + Sync {
+    fn read(&self, address: u32) -> Result<u16, Error>;
+    fn write(&self, address: u32, value: u16) -> Result<(), Error>;
+
+    /// Whether [`Backend::read_block`] is cheaper than reading registers one by one.
+    fn supports_block_read(&self) -> bool {
+        false
+    }
+
+    /// Reads `count` consecutive 16-bit registers starting at `address`.
+    fn read_block(&self, address: u32, count: usize) -> Result<Vec<u16>, Error> {
+        (0..count as u32)
+            .map(|i| self.read(address + i * REG_BYTES))
+            .collect()
+    }
+}
+
+/// Distance in bytes between two adjacent 16-bit registers.
+pub const REG_BYTES: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Timeout,
+    Disconnected,
+    Failed(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Timeout => f.write_str("timeout"),
+            Error::Disconnected => f.write_str("disconnected"),
+            Error::Failed(msg) => f.write_str(msg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    pub timeout: Duration,
+    /// Attempts after a timed out one, other errors aren't retried.
+    pub retries: u32,
+    /// Whether writes are retried too. A timed out write may still reach the
+    /// device, so retrying it can write twice.
+    pub retry_writes: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            timeout: Duration::from_millis(500),
+            retries: 2,
+            retry_writes: false,
+        }
+    }
+}
+
+pub async fn read(backend: Arc<dyn Backend>, config: Config, address: u32) -> Result<u16, Error> {
+    run(config.timeout, config.retries, move || {
+        backend.read(address)
+    })
+    .await
+}
+
+/// Reads `count` registers with [`Backend::read_block`], failing if the
+/// backend returns fewer values.
+pub async fn read_block(
+    backend: Arc<dyn Backend>,
+    config: Config,
+    address: u32,
+    count: usize,
+) -> Result<Vec<u16>, Error> {
+    let values = run(config.timeout, config.retries, move || {
+        backend.read_block(address, count)
+    })
+    .await?;
+    if values.len() < count {
+        return Err(Error::Failed(format!(
+            "block read returned {} of {} registers",
+            values.len(),
+            count
+        )));
+    }
+    Ok(values)
+}
+
+pub async fn write(
+    backend: Arc<dyn Backend>,
+    config: Config,
+    address: u32,
+    value: u16,
+) -> Result<(), Error> {
+    let retries = if config.retry_writes {
+        config.retries
+    } else {
+        0
+    };
+    run(config.timeout, retries, move || {
+        backend.write(address, value)
+    })
+    .await
+}
+
+/// Runs `op` on its own thread, trying again up to `retries` times while it
+/// times out.
+async fn run<T: Send + 'static>(
+    timeout: Duration,
+    retries: u32,
+    op: impl Fn() -> Result<T, Error> + Send + Sync + 'static,
+) -> Result<T, Error> {
+    let op = Arc::new(op);
+    let mut result = Err(Error::Timeout);
+    for _ in 0..=retries {
+        let (sender, receiver) = oneshot::channel();
+        let op = op.clone();
+        thread::spawn(move || {
+            let _ = sender.send(op());
+        });
+        result = match future::select(receiver, Delay::new(timeout)).await {
+            Either::Left((Ok(result), _)) => result,
+            Either::Left((Err(_), _)) => Err(Error::Disconnected),
+            Either::Right(_) => Err(Error::Timeout),
+        };
+        if !matches!(result, Err(Error::Timeout)) {
+            break;
+        }
+    }
+    result
+}
+
+/// In-memory register file standing in for a real target.
+#[derive(Default)]
+pub struct Simulator {
+    regs: Mutex<HashMap<u32, u16>>,
+    latency: Duration,
+}
+
+impl Simulator {
+    pub fn new(latency: Duration) -> Self {
+        Simulator {
+            regs: Mutex::new(HashMap::new()),
+            latency,
+        }
+    }
+
+    pub fn preset(&self, address: u32, value: u16) {
+        self.regs.lock().unwrap().insert(address, value);
+    }
+}
+
+impl Backend for Simulator {
+    fn read(&self, address: u32) -> Result<u16, Error> {
+        thread::sleep(self.latency);
+        let regs = self.regs.lock().map_err(|_| Error::Disconnected)?;
+        Ok(regs.get(&address).copied().unwrap_or(0))
+    }
+
+    fn write(&self, address: u32, value: u16) -> Result<(), Error> {
+        thread::sleep(self.latency);
+        let mut regs = self.regs.lock().map_err(|_| Error::Disconnected)?;
+        regs.insert(address, value);
+        Ok(())
+    }
+
+    fn supports_block_read(&self) -> bool {
+        true
+    }
+
+    fn read_block(&self, address: u32, count: usize) -> Result<Vec<u16>, Error> {
+        thread::sleep(self.latency);
+        let regs = self.regs.lock().map_err(|_| Error::Disconnected)?;
+        Ok((0..count as u32)
+            .map(|i| regs.get(&(address + i * REG_BYTES)).copied().unwrap_or(0))
+            .collect())
+    }
+}
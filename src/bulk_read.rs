@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+
+use crate::backend::{self, REG_BYTES};
+
+/// A register selected for a bulk read, identified by its peripheral and
+/// register index.
+#[derive(Debug, Clone, Copy)]
+pub struct Target {
+    pub periph: usize,
+    pub reg: usize,
+    pub address: u32,
+}
+
+/// Registers read together by a single backend call.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub address: u32,
+    pub targets: Vec<Target>,
+}
+
+pub struct BulkRead {
+    pub total: usize,
+    pub done: usize,
+    pub skipped: usize,
+    pub queue: VecDeque<Batch>,
+    pub current: Option<Batch>,
+    pub failures: Vec<(String, backend::Error)>,
+}
+
+impl BulkRead {
+    /// Splits `targets` into batches, merging registers at contiguous addresses
+    /// when `block_reads` is set.
+    pub fn new(mut targets: Vec<Target>, skipped: usize, block_reads: bool) -> Self {
+        targets.sort_by_key(|target| target.address);
+        let total = targets.len();
+        let mut queue: VecDeque<Batch> = VecDeque::new();
+        for target in targets {
+            match queue.back_mut() {
+                Some(batch)
+                    if block_reads
+                        && batch.address + batch.targets.len() as u32 * REG_BYTES
+                            == target.address =>
+                {
+                    batch.targets.push(target)
+                }
+                _ => queue.push_back(Batch {
+                    address: target.address,
+                    targets: vec![target],
+                }),
+            }
+        }
+        BulkRead {
+            total,
+            done: 0,
+            skipped,
+            queue,
+            current: None,
+            failures: vec![],
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current.is_none() && self.queue.is_empty()
+    }
+}
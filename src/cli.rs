@@ -0,0 +1,393 @@
+//! Headless commands reading and writing registers by their `PERIPH.REG.FIELD`
+//! path, for scripting board checks without the GUI.
+//!
+//! ```text
+//! iced_reg_tree [--svd FILE] [--patch FILE] read TIMER0.CR
+//! iced_reg_tree [--svd FILE] write TIMER0.CR.MODE=Single_MATCH
+//! iced_reg_tree [--svd FILE] dump [TIMER0]
+//! iced_reg_tree [--svd FILE] doc [md|html] [--values]
+//! iced_reg_tree [--svd FILE] convert
+//! iced_reg_tree [--svd FILE] repl
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::executor::block_on;
+
+use crate::backend::{self, Backend};
+use crate::bulk_read::{BulkRead, Target};
+use crate::doc::{self, DocFormat};
+use crate::expr::{self, Scope};
+use crate::format::{Format, InputError};
+use crate::model::{Device, Field, Peripheral, Register};
+use crate::regdesc;
+
+pub const USAGE: &str =
+    "usage: iced_reg_tree [--svd FILE] [--patch FILE] [repl | read PATH | write PATH=VALUE | dump [PERIPH] | doc [md|html] [--values] | convert]
+
+Commands run against the register simulator, starting from the reset values on
+every invocation: writes don't carry over to later commands and no hardware is
+accessed.";
+
+/// SVD file loaded when no `--svd` option is given.
+pub const DEFAULT_SVD: &str = "example.svd";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Read a `PERIPH.REG` register or a `PERIPH.REG.FIELD` field.
+    Read(String),
+    /// Write the expression to a register or a field, the latter by
+    /// read-modify-write.
+    Write { path: String, value: String },
+    /// Read all registers of a peripheral or the whole device.
+    Dump(Option<String>),
+    /// Print the register reference document, with the current values if set.
+    Doc { format: DocFormat, values: bool },
+    /// Print the register description in the lightweight YAML format.
+    Convert,
+}
+
+/// What the program was asked to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mode {
+    Gui,
+    /// Interactive console, see [`crate::repl`].
+    Repl,
+    Command(Command),
+}
+
+/// Parsed command line arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    pub svd: String,
+    /// svdtools style patch applied to the loaded description, see
+    /// [`crate::patch`].
+    pub patch: Option<String>,
+    pub mode: Mode,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Usage(String),
+    UnknownPath(String),
+    Input(InputError),
+    Expr(expr::Error),
+    Backend(backend::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Usage(msg) => f.write_str(msg),
+            Error::UnknownPath(path) => write!(f, "unknown register or field '{}'", path),
+            Error::Input(err) => write!(f, "{}", err),
+            Error::Expr(err) => write!(f, "{}", err),
+            Error::Backend(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<backend::Error> for Error {
+    fn from(err: backend::Error) -> Self {
+        Error::Backend(err)
+    }
+}
+
+/// Parses the arguments following the program name.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, Error> {
+    let mut args = args.into_iter();
+    let mut svd = DEFAULT_SVD.to_string();
+    let mut patch = None;
+    let mut mode = None;
+    while let Some(arg) = args.next() {
+        if mode.is_some() {
+            return Err(Error::Usage(format!("unexpected argument '{}'", arg)));
+        }
+        mode = Some(match arg.as_str() {
+            "--svd" => {
+                svd = args
+                    .next()
+                    .ok_or_else(|| Error::Usage("missing SVD file".to_string()))?;
+                continue;
+            }
+            "--patch" => {
+                patch = Some(
+                    args.next()
+                        .ok_or_else(|| Error::Usage("missing patch file".to_string()))?,
+                );
+                continue;
+            }
+            "repl" => Mode::Repl,
+            _ => Mode::Command(Command::parse(&arg, &mut args)?),
+        });
+    }
+    Ok(Args {
+        svd,
+        patch,
+        mode: mode.unwrap_or(Mode::Gui),
+    })
+}
+
+impl Command {
+    /// Parses the command `name` followed by its arguments. The value of a write
+    /// is the rest of the arguments joined by spaces, so it may be split by them.
+    pub fn parse(name: &str, args: &mut impl Iterator<Item = String>) -> Result<Command, Error> {
+        match name {
+            "read" => args
+                .next()
+                .map(Command::Read)
+                .ok_or_else(|| Error::Usage("missing register path".to_string())),
+            "write" => {
+                let arg = args.collect::<Vec<_>>().join(" ");
+                let (path, value) = arg
+                    .split_once('=')
+                    .ok_or_else(|| Error::Usage(format!("expected PATH=VALUE, got '{}'", arg)))?;
+                Ok(Command::Write {
+                    path: path.trim().to_string(),
+                    value: value.to_string(),
+                })
+            }
+            "dump" => Ok(Command::Dump(args.next())),
+            "doc" => {
+                let mut format = DocFormat::Markdown;
+                let mut values = false;
+                for arg in args {
+                    match arg.as_str() {
+                        "--values" => values = true,
+                        _ => format = arg.parse().map_err(Error::Usage)?,
+                    }
+                }
+                Ok(Command::Doc { format, values })
+            }
+            "convert" => Ok(Command::Convert),
+            _ => Err(Error::Usage(format!("unknown command '{}'", name))),
+        }
+    }
+}
+
+/// Register model and backend the commands run against, decoding values the
+/// same way as the GUI.
+pub struct Session {
+    pub device: Device,
+    pub backend: Arc<dyn Backend>,
+    pub config: backend::Config,
+    /// Display formats keyed by `PERIPH.REG.FIELD` path, as persisted by the GUI.
+    pub formats: HashMap<String, Format>,
+}
+
+impl Session {
+    /// Runs `command`, returning the decoded registers it read or wrote.
+    pub fn run(&self, command: &Command) -> Result<String, Error> {
+        match command {
+            Command::Read(path) => {
+                let (periph, reg, field) = self.lookup(path)?;
+                let value = block_on(backend::read(
+                    self.backend.clone(),
+                    self.config,
+                    reg.address,
+                ))?;
+                Ok(match field {
+                    Some(field) => format!(
+                        "{}.{}.{}\n",
+                        periph.name,
+                        reg.name,
+                        self.describe_field(periph, reg, field, value)
+                    ),
+                    None => self.describe(periph, reg, value),
+                })
+            }
+            Command::Write { path, value } => self.write(path, value),
+            Command::Dump(periph) => self.dump(periph.as_deref()),
+            Command::Doc { format, values } => Ok(self.doc(*format, *values)),
+            Command::Convert => Ok(regdesc::to_yaml(&self.device)),
+        }
+    }
+
+    /// Resolves a `PERIPH.REG` or `PERIPH.REG.FIELD` path.
+    fn lookup(&self, path: &str) -> Result<(&Peripheral, &Register, Option<&Field>), Error> {
+        let unknown = || Error::UnknownPath(path.to_string());
+        let mut parts = path.split('.');
+        let (Some(periph), Some(reg)) = (parts.next(), parts.next()) else {
+            return Err(unknown());
+        };
+        let periph = self.device.peripheral(periph).ok_or_else(unknown)?;
+        let reg = periph.register(reg).ok_or_else(unknown)?;
+        let field = match parts.next() {
+            Some(field) => Some(reg.field(field).ok_or_else(unknown)?),
+            None => None,
+        };
+        if parts.next().is_some() {
+            return Err(unknown());
+        }
+        Ok((periph, reg, field))
+    }
+
+    fn write(&self, path: &str, src: &str) -> Result<String, Error> {
+        let (periph, reg, field) = self.lookup(path)?;
+        let value = match field {
+            Some(field) => {
+                let read = block_on(backend::read(
+                    self.backend.clone(),
+                    self.config,
+                    reg.address,
+                ))?;
+                let value = self
+                    .format(periph, reg, field)
+                    .parse_input(src, field, field.decode(read))
+                    .map_err(Error::Input)?;
+                field.encode(read, value)
+            }
+            None => expr::eval(src, Scope::Register(&reg.fields)).map_err(Error::Expr)?,
+        };
+        block_on(backend::write(
+            self.backend.clone(),
+            self.config,
+            reg.address,
+            value,
+        ))?;
+        let value = block_on(backend::read(
+            self.backend.clone(),
+            self.config,
+            reg.address,
+        ))?;
+        Ok(self.describe(periph, reg, value))
+    }
+
+    /// Reads every register of `periph` or of the whole device, skipping the
+    /// ones with read side effects.
+    fn dump(&self, periph: Option<&str>) -> Result<String, Error> {
+        let periphs: Vec<usize> = match periph {
+            Some(name) => vec![self
+                .device
+                .peripherals
+                .iter()
+                .position(|periph| periph.name == name)
+                .ok_or_else(|| Error::UnknownPath(name.to_string()))?],
+            None => (0..self.device.peripherals.len()).collect(),
+        };
+        let (results, skipped) = self.read_all(periphs);
+        let mut out = String::new();
+        for (target, result) in results {
+            let periph = &self.device.peripherals[target.periph];
+            let reg = &periph.registers[target.reg];
+            match result {
+                Ok(value) => out += self.describe(periph, reg, value).as_str(),
+                Err(err) => out += format!("{}.{}: {}\n", periph.name, reg.name, err).as_str(),
+            }
+        }
+        for target in skipped {
+            let periph = &self.device.peripherals[target.periph];
+            out += format!(
+                "{}.{}: skipped, reading has side effects\n",
+                periph.name, periph.registers[target.reg].name
+            )
+            .as_str();
+        }
+        Ok(out)
+    }
+
+    /// Reads every register of the peripherals `periphs` in address order,
+    /// returning the ones with read side effects as skipped.
+    #[allow(clippy::type_complexity)]
+    fn read_all(
+        &self,
+        periphs: Vec<usize>,
+    ) -> (Vec<(Target, Result<u16, backend::Error>)>, Vec<Target>) {
+        let mut targets = vec![];
+        let mut skipped = vec![];
+        for p in periphs {
+            for (idx, reg) in self.device.peripherals[p].registers.iter().enumerate() {
+                let target = Target {
+                    periph: p,
+                    reg: idx,
+                    address: reg.address,
+                };
+                if reg.read_side_effects {
+                    skipped.push(target);
+                } else {
+                    targets.push(target);
+                }
+            }
+        }
+        let mut bulk_read =
+            BulkRead::new(targets, skipped.len(), self.backend.supports_block_read());
+        let mut results = vec![];
+        while let Some(batch) = bulk_read.queue.pop_front() {
+            let result = block_on(backend::read_block(
+                self.backend.clone(),
+                self.config,
+                batch.address,
+                batch.targets.len(),
+            ));
+            for (i, target) in batch.targets.into_iter().enumerate() {
+                results.push((
+                    target,
+                    result
+                        .as_ref()
+                        .map(|values| values[i])
+                        .map_err(Clone::clone),
+                ));
+            }
+        }
+        (results, skipped)
+    }
+
+    /// Reference document of the device, with the current values of the
+    /// registers without read side effects if `values` is set.
+    fn doc(&self, format: DocFormat, values: bool) -> String {
+        let mut current = doc::Values::new();
+        if values {
+            let (results, _) = self.read_all((0..self.device.peripherals.len()).collect());
+            for (target, result) in results {
+                if let Ok(value) = result {
+                    current.insert(target.address, value);
+                }
+            }
+        }
+        doc::render(&self.device, &current, format)
+    }
+
+    fn format(&self, periph: &Peripheral, reg: &Register, field: &Field) -> Format {
+        self.formats
+            .get(&format!("{}.{}.{}", periph.name, reg.name, field.name))
+            .cloned()
+            .unwrap_or(Format::default_for_width(field.width))
+    }
+
+    /// The register value followed by its decoded fields, one per line.
+    pub fn describe(&self, periph: &Peripheral, reg: &Register, value: u16) -> String {
+        let mut out = format!(
+            "{}.{} = {}\n",
+            periph.name,
+            reg.name,
+            Format::Hex.format(value, 16)
+        );
+        for field in reg.fields.iter() {
+            out += format!("  {}\n", self.describe_field(periph, reg, field, value)).as_str();
+        }
+        out
+    }
+
+    /// `FIELD = value`, with the enumerated value name if there's one.
+    pub fn describe_field(
+        &self,
+        periph: &Peripheral,
+        reg: &Register,
+        field: &Field,
+        reg_value: u16,
+    ) -> String {
+        let value = field.decode(reg_value);
+        let mut out = format!(
+            "{} = {}",
+            field.name,
+            self.format(periph, reg, field).format(value, field.width)
+        );
+        if let Some(name) = field.value_name(value) {
+            out += format!(" ({})", name).as_str();
+        }
+        out
+    }
+}
@@ -17,6 +17,9 @@ use iced::overlay::menu;
 use iced::advanced::text::LineHeight;
 use iced::widget::text_input::{self, TextInput};
 
+use crate::menu::Describe;
+use crate::model::parse_number;
+
 use std::cell::RefCell;
 use std::fmt::Display;
 
@@ -39,12 +42,13 @@ pub struct ComboBox<
     state: &'a State<T>,
     text_input: TextInput<'a, TextInputEvent, Theme, Renderer>,
     font: Option<Renderer::Font>,
-    _selection: text_input::Value,
+    selection: text_input::Value,
     on_selected: Box<dyn Fn(T) -> Message>,
     on_option_hovered: Option<Box<dyn Fn(T) -> Message>>,
     on_close: Option<Message>,
     on_input: Option<Box<dyn Fn(String) -> Message>>,
     menu_class: <Theme as menu::Catalog>::Class<'a>,
+    menu_width: Option<f32>,
     padding: Padding,
     size: Option<f32>,
 }
@@ -74,12 +78,13 @@ where
             state,
             text_input,
             font: None,
-            _selection: text_input::Value::new(&selection),
+            selection: text_input::Value::new(&selection),
             on_selected: Box::new(on_selected),
             on_option_hovered: None,
             on_input: None,
             on_close: None,
             menu_class: <Theme as Catalog>::default_menu(),
+            menu_width: None,
             padding: text_input::DEFAULT_PADDING,
             size: None,
         }
@@ -157,6 +162,15 @@ where
         }
     }
 
+    /// Sets the minimum width of the menu of the [`ComboBox`], which is
+    /// otherwise as wide as its input.
+    pub fn menu_width(self, width: f32) -> Self {
+        Self {
+            menu_width: Some(width),
+            ..self
+        }
+    }
+
     /// Sets the style of the input of the [`ComboBox`].
     #[must_use]
     pub fn input_style(
@@ -196,13 +210,15 @@ pub struct State<T> {
 #[derive(Debug, Clone)]
 struct Inner<T> {
     value: String,
-    option_matchers: Vec<String>,
+    option_matchers: Vec<Vec<char>>,
     filtered_options: Filtered<T>,
 }
 
 #[derive(Debug, Clone)]
 struct Filtered<T> {
     options: Vec<T>,
+    /// Indices of the matched characters of every option.
+    highlights: Vec<Vec<usize>>,
     updated: Instant,
 }
 
@@ -223,11 +239,9 @@ where
         // Pre-build "matcher" strings ahead of time so that search is fast
         let option_matchers = build_matchers(&options);
 
-        let filtered_options = Filtered::new(
-            search(&options, &option_matchers, &value)
-                .cloned()
-                .collect(),
-        );
+        let (filtered, highlights) =
+            search(&options, &option_matchers, &value);
+        let filtered_options = Filtered::new(filtered, highlights);
 
         Self {
             options,
@@ -245,11 +259,9 @@ where
         // Pre-build "matcher" strings ahead of time so that search is fast
         let option_matchers = build_matchers(&options);
 
-        let filtered_options = Filtered::new(
-            search(&options, &option_matchers, &value)
-                .cloned()
-                .collect(),
-        );
+        let (filtered, highlights) =
+            search(&options, &option_matchers, &value);
+        let filtered_options = Filtered::new(filtered, highlights);
 
         Self {
             options,
@@ -307,9 +319,10 @@ impl<T> Filtered<T>
 where
     T: Clone,
 {
-    fn new(options: Vec<T>) -> Self {
+    fn new(options: Vec<T>, highlights: Vec<Vec<usize>>) -> Self {
         Self {
             options,
+            highlights,
             updated: Instant::now(),
         }
     }
@@ -317,12 +330,14 @@ where
     fn empty() -> Self {
         Self {
             options: vec![],
+            highlights: vec![],
             updated: Instant::now(),
         }
     }
 
-    fn update(&mut self, options: Vec<T>) {
+    fn update(&mut self, options: Vec<T>, highlights: Vec<Vec<usize>>) {
         self.options = options;
+        self.highlights = highlights;
         self.updated = Instant::now();
     }
 
@@ -334,7 +349,7 @@ where
 }
 
 struct Menu<T> {
-    menu: menu::State,
+    menu: crate::menu::State,
     hovered_option: Option<usize>,
     new_selection: Option<T>,
     filtered_options: Filtered<T>,
@@ -348,23 +363,29 @@ enum TextInputEvent {
 impl<'a, T, Message, Theme, Renderer> Widget<Message, Theme, Renderer>
     for ComboBox<'a, T, Message, Theme, Renderer>
 where
-    T: Display + Clone + 'static,
+    T: Display + Describe + Clone + 'static,
     Message: Clone,
     Theme: Catalog,
     Renderer: text::Renderer,
 {
     fn size(&self) -> Size<Length> {
-        Size { width: Length::Fixed(0.0), height: Length::Fixed(0.0) }
+        Widget::<TextInputEvent, Theme, Renderer>::size(&self.text_input)
     }
 
     fn layout(
         &self,
-        _tree: &mut widget::Tree,
-        _renderer: &Renderer,
-        _limits: &layout::Limits,
+        tree: &mut widget::Tree,
+        renderer: &Renderer,
+        limits: &layout::Limits,
     ) -> layout::Node {
+        let is_focused = text_input_focused::<Renderer>(tree);
 
-        layout::Node::new(Size { width: 100.0, height: 0.0 })
+        self.text_input.layout(
+            &mut tree.children[0],
+            renderer,
+            limits,
+            (!is_focused).then_some(&self.selection),
+        )
     }
 
     fn tag(&self) -> widget::tree::Tag {
@@ -373,7 +394,7 @@ where
 
     fn state(&self) -> widget::tree::State {
         widget::tree::State::new(Menu::<T> {
-            menu: menu::State::new(),
+            menu: crate::menu::State::new(),
             filtered_options: Filtered::empty(),
             hovered_option: Some(0),
             new_selection: None,
@@ -388,22 +409,36 @@ where
         &mut self,
         tree: &mut widget::Tree,
         event: Event,
-        _layout: Layout<'_>,
-        _cursor: mouse::Cursor,
-        _renderer: &Renderer,
-        _clipboard: &mut dyn Clipboard,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
         shell: &mut Shell<'_, Message>,
-        _viewport: &Rectangle,
+        viewport: &Rectangle,
     ) -> event::Status {
-        let menu = tree.state.downcast_mut::<Menu<T>>();
-
-        let started_focused = true;
+        let started_focused = text_input_focused::<Renderer>(tree);
         // This is intended to check whether or not the message buffer was empty,
         // since `Shell` does not expose such functionality.
         let mut published_message_to_shell = false;
 
         // Create a new list of local messages
-        let local_messages = Vec::new();
+        let mut local_messages = Vec::new();
+        let mut local_shell = Shell::new(&mut local_messages);
+
+        // Provide it to the widget
+        let mut event_status = self.text_input.on_event(
+            &mut tree.children[0],
+            event.clone(),
+            layout,
+            cursor,
+            renderer,
+            clipboard,
+            &mut local_shell,
+            viewport,
+        );
+
+        let is_focused = text_input_focused::<Renderer>(tree);
+        let menu = tree.state.downcast_mut::<Menu<T>>();
 
         // Then finally react to them here
         for message in local_messages {
@@ -421,22 +456,16 @@ where
                 menu.hovered_option = Some(0);
                 state.value = new_value;
 
-                state.filtered_options.update(
-                    search(
-                        &self.state.options,
-                        &state.option_matchers,
-                        &state.value,
-                    )
-                    .cloned()
-                    .collect(),
+                let (filtered, highlights) = search(
+                    &self.state.options,
+                    &state.option_matchers,
+                    &state.value,
                 );
+                state.filtered_options.update(filtered, highlights);
             });
             shell.invalidate_layout();
         }
 
-        let is_focused = true;
-        let mut event_status = event::Status::Ignored;
-
         if is_focused {
             self.state.with_inner(|state| {
                 if !started_focused {
@@ -567,17 +596,32 @@ where
             if let Some(selection) = menu.new_selection.take() {
                 // Clear the value and reset the options and menu
                 state.value = String::new();
-                state.filtered_options.update(self.state.options.clone());
-                menu.menu = menu::State::default();
+                state
+                    .filtered_options
+                    .update(self.state.options.clone(), vec![]);
+                menu.menu = crate::menu::State::default();
 
                 // Notify the selection
                 shell.publish((self.on_selected)(selection));
                 published_message_to_shell = true;
 
+                // Unfocus the input
+                let _ = self.text_input.on_event(
+                    &mut tree.children[0],
+                    Event::Mouse(mouse::Event::ButtonPressed(
+                        mouse::Button::Left,
+                    )),
+                    layout,
+                    mouse::Cursor::Unavailable,
+                    renderer,
+                    clipboard,
+                    &mut Shell::new(&mut vec![]),
+                    viewport,
+                );
             }
         });
 
-        let is_focused = true;
+        let is_focused = text_input_focused::<Renderer>(tree);
 
         if started_focused && !is_focused && !published_message_to_shell {
             if let Some(message) = self.on_close.take() {
@@ -593,18 +637,49 @@ where
         event_status
     }
 
+    fn mouse_interaction(
+        &self,
+        tree: &widget::Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.text_input.mouse_interaction(
+            &tree.children[0],
+            layout,
+            cursor,
+            viewport,
+            renderer,
+        )
+    }
 
     fn draw(
         &self,
-        _tree: &widget::Tree,
-        _renderer: &mut Renderer,
-        _theme: &Theme,
+        tree: &widget::Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
         _style: &renderer::Style,
-        _layout: Layout<'_>,
-        _cursor: mouse::Cursor,
-        _viewport: &Rectangle,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
     ) {
-
+        let selection = if text_input_focused::<Renderer>(tree) || self.selection.is_empty()
+        {
+            None
+        } else {
+            Some(&self.selection)
+        };
+
+        self.text_input.draw(
+            &tree.children[0],
+            renderer,
+            theme,
+            layout,
+            cursor,
+            selection,
+            viewport,
+        );
     }
 
     fn overlay<'b>(
@@ -614,9 +689,7 @@ where
         _renderer: &Renderer,
         translation: Vector,
     ) -> Option<overlay::Element<'b, Message, Theme, Renderer>> {
-        let is_focused = true;
-
-        if is_focused {
+        if text_input_focused::<Renderer>(tree) {
             let Menu {
                 menu,
                 filtered_options,
@@ -631,9 +704,10 @@ where
             } else {
                 let bounds = layout.bounds();
 
-                let mut menu = menu::Menu::new(
+                let mut menu = crate::menu::Menu::new(
                     menu,
                     &filtered_options.options,
+                    &filtered_options.highlights,
                     hovered_option,
                     |x| {
                         tree.children[0]
@@ -647,7 +721,7 @@ where
                     self.on_option_hovered.as_deref(),
                     &self.menu_class,
                 )
-                .width(bounds.width)
+                .width(self.menu_width.unwrap_or(0.0).max(bounds.width))
                 .padding(self.padding);
 
                 if let Some(font) = self.font {
@@ -675,7 +749,7 @@ impl<'a, T, Message, Theme, Renderer>
     From<ComboBox<'a, T, Message, Theme, Renderer>>
     for Element<'a, Message, Theme, Renderer>
 where
-    T: Display + Clone + 'static,
+    T: Display + Describe + Clone + 'static,
     Message: Clone + 'a,
     Theme: Catalog + 'a,
     Renderer: text::Renderer + 'a,
@@ -700,45 +774,148 @@ pub trait Catalog: text_input::Catalog + menu::Catalog {
 
 impl Catalog for Theme {}
 
-fn search<'a, T, A>(
-    options: impl IntoIterator<Item = T> + 'a,
-    option_matchers: impl IntoIterator<Item = &'a A> + 'a,
-    query: &'a str,
-) -> impl Iterator<Item = T> + 'a
+fn text_input_focused<Renderer>(tree: &widget::Tree) -> bool
+where
+    Renderer: text::Renderer,
+{
+    tree.children[0]
+        .state
+        .downcast_ref::<text_input::State<Renderer::Paragraph>>()
+        .is_focused()
+}
+
+/// Ranks the options matching every whitespace separated part of the query,
+/// best matches first, along with the indices of their matched characters.
+fn search<T>(
+    options: &[T],
+    option_matchers: &[Vec<char>],
+    query: &str,
+) -> (Vec<T>, Vec<Vec<usize>>)
 where
-    A: AsRef<str> + 'a,
+    T: Clone,
 {
-    let query: Vec<String> = query
-        .to_lowercase()
-        .split(|c: char| !c.is_ascii_alphanumeric())
-        .map(String::from)
+    let query: Vec<Vec<char>> = query
+        .split_whitespace()
+        .map(|part| part.chars().map(|c| c.to_ascii_lowercase()).collect())
         .collect();
 
-    options
-        .into_iter()
+    let mut matches: Vec<(usize, &T, Vec<usize>)> = options
+        .iter()
         .zip(option_matchers)
         // Make sure each part of the query is found in the option
-        .filter_map(move |(option, matcher)| {
-            if query.iter().all(|part| matcher.as_ref().contains(part)) {
-                Some(option)
-            } else {
-                None
+        .filter_map(|(option, matcher)| {
+            let mut score = 0;
+            let mut highlights = vec![];
+            for part in &query {
+                let (part_score, indices) = fuzzy_match(matcher, part)?;
+                score += part_score;
+                highlights.extend(indices);
             }
+            highlights.sort_unstable();
+            highlights.dedup();
+            Some((score, option, highlights))
         })
+        .collect();
+
+    // Stable, so equally good matches keep the order of the options
+    matches.sort_by_key(|(score, _, _)| *score);
+
+    matches
+        .into_iter()
+        .map(|(_, option, highlights)| (option.clone(), highlights))
+        .unzip()
+}
+
+/// Matches a lowercase query part against the characters of an option.
+///
+/// Returns a score, lower is better, and the indices of the matched
+/// characters. A number equal to a numeric token of the option ranks first,
+/// then a prefix of a whitespace separated token, then a prefix of a word
+/// inside a token (after `_`, at a camelCase or letter/digit change) and last
+/// a subsequence, the shorter its span the better.
+fn fuzzy_match(option: &[char], part: &[char]) -> Option<(usize, Vec<usize>)> {
+    const TIER: usize = 1000;
+
+    let lower: Vec<char> =
+        option.iter().map(char::to_ascii_lowercase).collect();
+    let starts_with = |start: &usize| lower[*start..].starts_with(part);
+    let matched = |start: usize| (start..start + part.len()).collect();
+
+    if let Some(value) = parse_number(&part.iter().collect::<String>()) {
+        let mut start = 0;
+        while start < lower.len() {
+            let end = lower[start..]
+                .iter()
+                .position(|c| !c.is_ascii_alphanumeric())
+                .map_or(lower.len(), |len| start + len);
+            let token: String = lower[start..end].iter().collect();
+            if parse_number(&token) == Some(value) {
+                return Some((0, (start..end).collect()));
+            }
+            start = end + 1;
+        }
+    }
+
+    if let Some(start) = (0..lower.len())
+        .filter(|&i| i == 0 || option[i - 1].is_whitespace())
+        .find(starts_with)
+    {
+        return Some((TIER + start, matched(start)));
+    }
+
+    if let Some(start) =
+        (0..lower.len()).filter(|&i| is_word_start(option, i)).find(starts_with)
+    {
+        return Some((2 * TIER + start, matched(start)));
+    }
+
+    let first = *part.first()?;
+    let mut best: Option<Vec<usize>> = None;
+    for start in (0..lower.len()).filter(|&i| lower[i] == first) {
+        let mut indices = vec![start];
+        for c in &part[1..] {
+            let from = indices[indices.len() - 1] + 1;
+            match lower[from..].iter().position(|o| o == c) {
+                Some(offset) => indices.push(from + offset),
+                None => break,
+            }
+        }
+        if indices.len() < part.len() {
+            // Starting any later can't match either
+            break;
+        }
+        if best.as_ref().is_none_or(|best| span(&indices) < span(best)) {
+            best = Some(indices);
+        }
+    }
+    best.map(|indices| (3 * TIER + span(&indices), indices))
+}
+
+fn is_word_start(option: &[char], i: usize) -> bool {
+    let c = option[i];
+    if !c.is_alphanumeric() {
+        return false;
+    }
+    let Some(&prev) = i.checked_sub(1).and_then(|i| option.get(i)) else {
+        return true;
+    };
+    !prev.is_alphanumeric()
+        || (prev.is_lowercase() && c.is_uppercase())
+        || prev.is_ascii_digit() != c.is_ascii_digit()
+}
+
+fn span(indices: &[usize]) -> usize {
+    indices[indices.len() - 1] - indices[0]
 }
 
 fn build_matchers<'a, T>(
     options: impl IntoIterator<Item = T> + 'a,
-) -> Vec<String>
+) -> Vec<Vec<char>>
 where
     T: Display + 'a,
 {
     options
         .into_iter()
-        .map(|opt| {
-            let mut matcher = opt.to_string();
-            matcher.retain(|c| c.is_ascii_alphanumeric());
-            matcher.to_lowercase()
-        })
+        .map(|opt| opt.to_string().chars().collect())
         .collect()
 }
@@ -0,0 +1,326 @@
+//! Register reference documents rendered from the device model, as Markdown or
+//! standalone HTML.
+//!
+//! Each register gets a bit diagram and a table of its fields with their
+//! enumerated values. When register values are given, they're decoded next to
+//! the fields.
+
+use std::collections::HashMap;
+
+use crate::format::Format;
+use crate::model::{Device, Field, Register, REG_BITS};
+
+/// Files the GUI writes the documents to.
+pub const MARKDOWN_FILE: &str = "registers.md";
+pub const HTML_FILE: &str = "registers.html";
+
+/// Current register values keyed by address, registers missing in it are
+/// documented without a value.
+pub type Values = HashMap<u32, u16>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+impl std::str::FromStr for DocFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md" | "markdown" => Ok(DocFormat::Markdown),
+            "html" => Ok(DocFormat::Html),
+            _ => Err(format!("unknown document format '{}'", s)),
+        }
+    }
+}
+
+pub fn render(device: &Device, values: &Values, format: DocFormat) -> String {
+    match format {
+        DocFormat::Markdown => markdown(device, values),
+        DocFormat::Html => html(device, values),
+    }
+}
+
+/// Consecutive bits of a register from the most significant one, covered by a
+/// field or reserved.
+struct Segment<'a> {
+    msb: u8,
+    lsb: u8,
+    field: Option<&'a Field>,
+}
+
+fn segments(reg: &Register) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut bit = REG_BITS;
+    while bit > 0 {
+        let msb = bit - 1;
+        let field = reg
+            .fields
+            .iter()
+            .find(|field| (field.offset..field.offset + field.width).contains(&msb));
+        let lsb = match field {
+            Some(field) => field.offset,
+            None => {
+                // Down to the next field or bit 0
+                let mut lsb = msb;
+                while lsb > 0
+                    && !reg
+                        .fields
+                        .iter()
+                        .any(|field| field.mask() & (1 << (lsb - 1)) != 0)
+                {
+                    lsb -= 1;
+                }
+                lsb
+            }
+        };
+        segments.push(Segment { msb, lsb, field });
+        bit = lsb;
+    }
+    segments
+}
+
+fn bits(msb: u8, lsb: u8) -> String {
+    if msb == lsb {
+        msb.to_string()
+    } else {
+        format!("{}:{}", msb, lsb)
+    }
+}
+
+fn hex(value: u32, width: u8) -> String {
+    format!("0x{:0w$X}", value, w = (width as usize).div_ceil(4))
+}
+
+/// The field value in its default format, with its name if it has one.
+fn decoded(field: &Field, value: u16) -> String {
+    let text = Format::default_for_width(field.width).format(value, field.width);
+    match field.value_name(value) {
+        Some(name) => format!("{} ({})", text, name),
+        None => text,
+    }
+}
+
+fn markdown(device: &Device, values: &Values) -> String {
+    let mut out = format!("# {} registers\n", device.name);
+    for periph in device.peripherals.iter() {
+        out += format!("\n## {}\n\n", periph.name).as_str();
+        if let Some(description) = &periph.description {
+            out += format!("{}\n\n", one_line(description)).as_str();
+        }
+        out += format!("Base address `{}`\n", hex(periph.base_address, 32)).as_str();
+
+        for reg in periph.registers.iter() {
+            let value = values.get(&reg.address).copied();
+            out += format!("\n### {}.{}\n\n", periph.name, reg.name).as_str();
+            if let Some(description) = &reg.description {
+                out += format!("{}\n\n", one_line(description)).as_str();
+            }
+            out += format!(
+                "Address `{}`, reset value `{}`",
+                hex(reg.address, 32),
+                hex(reg.reset_value as u32, REG_BITS)
+            )
+            .as_str();
+            if let Some(value) = value {
+                out += format!(", current value `{}`", hex(value as u32, REG_BITS)).as_str();
+            }
+            out += "\n\n```text\n";
+            out += bit_diagram(reg).as_str();
+            out += "```\n\n";
+
+            let (current, rule) = match value {
+                Some(_) => (" Current |", "---------|"),
+                None => ("", ""),
+            };
+            out += format!(
+                "| Field | Bits | Description | Values |{}\n|-------|------|-------------|--------|{}\n",
+                current, rule
+            )
+            .as_str();
+            // Zero-width fields, which SVD files may have, take up no bits
+            for field in reg.fields.iter().rev().filter(|field| field.width > 0) {
+                let enum_values: Vec<String> = field
+                    .enum_values
+                    .iter()
+                    .map(|val| format!("{} = {}", val.value, cell(&val.name)))
+                    .collect();
+                out += format!(
+                    "| {} | {} | {} | {} |",
+                    field.name,
+                    bits(field.offset + field.width - 1, field.offset),
+                    field.description.as_deref().map(cell).unwrap_or_default(),
+                    enum_values.join("<br>")
+                )
+                .as_str();
+                match value {
+                    Some(value) => {
+                        out +=
+                            format!(" {} |\n", cell(&decoded(field, field.decode(value)))).as_str()
+                    }
+                    None => out += "\n",
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Bit numbers over the field names, four characters per bit:
+///
+/// ```text
+///  15  14  13  12  11  10   9   8   7   6   5   4   3   2   1   0
+/// |    CAPSRC     |    CNTSRC     |PSC|   MODE    |  CNT  |RST|EN |
+/// ```
+fn bit_diagram(reg: &Register) -> String {
+    let mut numbers = String::new();
+    for bit in (0..REG_BITS).rev() {
+        numbers += format!("{:>3} ", bit).as_str();
+    }
+    let mut names = String::from("|");
+    for segment in segments(reg) {
+        let width = (segment.msb - segment.lsb + 1) as usize * 4 - 1;
+        let name = segment.field.map_or("-", |field| field.name.as_str());
+        let name: String = name.chars().take(width).collect();
+        names += format!("{:^w$}|", name, w = width).as_str();
+    }
+    format!("{}\n{}\n", numbers.trim_end(), names)
+}
+
+fn html(device: &Device, values: &Values) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{} registers</title>\n<style>\n{}</style>\n</head>\n\
+         <body>\n<h1>{} registers</h1>\n",
+        escape(&device.name),
+        STYLE,
+        escape(&device.name)
+    );
+    for periph in device.peripherals.iter() {
+        out += format!("<h2 id=\"{0}\">{0}</h2>\n", escape(&periph.name)).as_str();
+        if let Some(description) = &periph.description {
+            out += format!("<p>{}</p>\n", escape(description)).as_str();
+        }
+        out += format!(
+            "<p>Base address <code>{}</code></p>\n",
+            hex(periph.base_address, 32)
+        )
+        .as_str();
+
+        for reg in periph.registers.iter() {
+            let value = values.get(&reg.address).copied();
+            out += format!(
+                "<h3 id=\"{0}.{1}\">{0}.{1}</h3>\n",
+                escape(&periph.name),
+                escape(&reg.name)
+            )
+            .as_str();
+            if let Some(description) = &reg.description {
+                out += format!("<p>{}</p>\n", escape(description)).as_str();
+            }
+            out += format!(
+                "<p>Address <code>{}</code>, reset value <code>{}</code>",
+                hex(reg.address, 32),
+                hex(reg.reset_value as u32, REG_BITS)
+            )
+            .as_str();
+            if let Some(value) = value {
+                out += format!(
+                    ", current value <code>{}</code>",
+                    hex(value as u32, REG_BITS)
+                )
+                .as_str();
+            }
+            out += "</p>\n";
+
+            out += "<table class=\"bits\">\n<tr>";
+            for bit in (0..REG_BITS).rev() {
+                out += format!("<th>{}</th>", bit).as_str();
+            }
+            out += "</tr>\n<tr>";
+            for segment in segments(reg) {
+                let span = segment.msb - segment.lsb + 1;
+                match segment.field {
+                    Some(field) => {
+                        out += format!("<td colspan=\"{}\">{}", span, escape(&field.name)).as_str();
+                        if let Some(value) = value {
+                            out += format!(
+                                "<br><code>{}</code>",
+                                hex(field.decode(value) as u32, field.width)
+                            )
+                            .as_str();
+                        }
+                        out += "</td>";
+                    }
+                    None => {
+                        out += format!("<td colspan=\"{}\" class=\"reserved\"></td>", span).as_str()
+                    }
+                }
+            }
+            out += "</tr>\n</table>\n";
+
+            out += "<table>\n<tr><th>Field</th><th>Bits</th><th>Description</th><th>Values</th>";
+            if value.is_some() {
+                out += "<th>Current</th>";
+            }
+            out += "</tr>\n";
+            for field in reg.fields.iter().rev().filter(|field| field.width > 0) {
+                let enum_values: Vec<String> = field
+                    .enum_values
+                    .iter()
+                    .map(|val| {
+                        let mut item = format!("{} = {}", val.value, escape(&val.name));
+                        if let Some(description) = &val.description {
+                            item += format!(" <span class=\"note\">{}</span>", escape(description))
+                                .as_str();
+                        }
+                        item
+                    })
+                    .collect();
+                out += format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td>",
+                    escape(&field.name),
+                    bits(field.offset + field.width - 1, field.offset),
+                    field.description.as_deref().map(escape).unwrap_or_default(),
+                    enum_values.join("<br>")
+                )
+                .as_str();
+                if let Some(value) = value {
+                    out += format!("<td>{}</td>", escape(&decoded(field, field.decode(value))))
+                        .as_str();
+                }
+                out += "</tr>\n";
+            }
+            out += "</table>\n";
+        }
+    }
+    out + "</body>\n</html>\n"
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 60em; margin: auto; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #999; padding: 2px 6px; text-align: left; }
+table.bits td, table.bits th { text-align: center; min-width: 2em; }
+td.reserved { background: #ddd; }
+.note { color: #666; }
+";
+
+/// Description text on a single line.
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Text of a Markdown table cell.
+fn cell(text: &str) -> String {
+    one_line(text).replace('|', "\\|")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,182 @@
+//! Register values exported as firmware source code, C with CMSIS-like names
+//! or Rust for svd2rust peripheral access crates.
+
+use crate::model::Register;
+
+/// C statements assigning `value` to each register, CMSIS style:
+///
+/// ```c
+/// TIMER0->CR = (1 << TIMER0_CR_EN_Pos)      /* Enable */
+///            | (2 << TIMER0_CR_MODE_Pos);   /* Single_MATCH */
+/// ```
+///
+/// The `_Pos` names are derived from the peripheral, register and field names.
+/// Bits outside the fields are added as a plain literal.
+pub fn c_code<'a>(regs: impl IntoIterator<Item = (&'a str, &'a Register, u16)>) -> String {
+    let mut out = String::new();
+    for (periph, reg, value) in regs {
+        let prefix = format!("{}_{}", c_name(periph), c_name(&reg.name));
+        let mut terms: Vec<(String, Option<String>)> = reg
+            .decode(value)
+            .filter(|(_, val)| *val != 0)
+            .map(|(field, val)| {
+                (
+                    format!(
+                        "({} << {}_{}_Pos)",
+                        c_literal(val),
+                        prefix,
+                        c_name(&field.name)
+                    ),
+                    field.value_name(val),
+                )
+            })
+            .collect();
+        let other = value & !reg.fields.iter().fold(0, |mask, field| mask | field.mask());
+        if other != 0 {
+            terms.push((format!("0x{:04X}", other), None));
+        }
+        if terms.is_empty() {
+            terms.push((String::from("0"), None));
+        }
+
+        let lhs = format!("{}->{} = ", c_name(periph), c_name(&reg.name));
+        let width = lhs.len() + terms.iter().map(|(term, _)| term.len()).max().unwrap_or(0) + 1;
+        for (i, (term, comment)) in terms.iter().enumerate() {
+            let mut line = if i == 0 {
+                lhs.clone()
+            } else {
+                format!("{:>w$}", "| ", w = lhs.len())
+            };
+            line += term;
+            if i + 1 == terms.len() {
+                line += ";";
+            }
+            match comment {
+                Some(comment) => {
+                    out += format!("{:<w$}  /* {} */\n", line, comment, w = width).as_str()
+                }
+                None => out += format!("{}\n", line).as_str(),
+            }
+        }
+    }
+    out
+}
+
+/// Rust statements writing `value` to each register through an svd2rust
+/// peripheral access crate:
+///
+/// ```text
+/// timer0.cr.write(|w| w.en().enable().mode().single_match());
+/// ```
+///
+/// `write` starts from the reset value, so only the fields differing from it are
+/// set, by their enumerated value if there's one and `bits` otherwise.
+pub fn rust_code<'a>(regs: impl IntoIterator<Item = (&'a str, &'a Register, u16)>) -> String {
+    let mut out = String::new();
+    for (periph, reg, value) in regs {
+        let periph = rust_name(periph);
+        let name = rust_name(&reg.name);
+        let fields_mask = reg.fields.iter().fold(0, |mask, field| mask | field.mask());
+        if (value ^ reg.reset_value) & !fields_mask != 0 {
+            // Bits outside the fields have no writer
+            out += format!(
+                "{}.{}.write(|w| unsafe {{ w.bits(0x{:04X}) }});\n",
+                periph, name, value
+            )
+            .as_str();
+            continue;
+        }
+        let mut unsafe_bits = false;
+        let writers: Vec<String> = reg
+            .decode(value)
+            .filter(|(field, val)| field.decode(reg.reset_value) != *val)
+            .map(|(field, val)| {
+                let writer = match field.enum_value(val) {
+                    Some(enum_value) => format!("{}()", rust_name(&enum_value.name)),
+                    None if field.width == 1 && val == 1 => String::from("set_bit()"),
+                    None if field.width == 1 => String::from("clear_bit()"),
+                    None => {
+                        unsafe_bits = true;
+                        format!("bits({})", c_literal(val))
+                    }
+                };
+                format!(".{}().{}", rust_name(&field.name), writer)
+            })
+            .collect();
+        if writers.is_empty() {
+            out += format!("{}.{}.reset();\n", periph, name).as_str();
+        } else if unsafe_bits {
+            out += format!(
+                "{}.{}.write(|w| unsafe {{ w{} }});\n",
+                periph,
+                name,
+                writers.concat()
+            )
+            .as_str();
+        } else {
+            out += format!("{}.{}.write(|w| w{});\n", periph, name, writers.concat()).as_str();
+        }
+    }
+    out
+}
+
+/// Identifier of an SVD name in Rust source as svd2rust derives it, snake case.
+fn rust_name(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !out.is_empty() && !out.ends_with('_') {
+                out.push('_');
+            }
+            continue;
+        }
+        if c.is_ascii_uppercase() && i > 0 && !out.ends_with('_') {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    let out = out.trim_end_matches('_').to_string();
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", out)
+    } else if RUST_KEYWORDS.contains(&out.as_str()) {
+        out + "_"
+    } else {
+        out
+    }
+}
+
+const RUST_KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "yield",
+];
+
+/// Identifier of an SVD name in C source, upper case.
+fn c_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn c_literal(value: u16) -> String {
+    if value < 10 {
+        value.to_string()
+    } else {
+        format!("0x{:X}", value)
+    }
+}
@@ -0,0 +1,313 @@
+//! Evaluation of value expressions typed into register and field inputs.
+//!
+//! Expressions use C operators and precedence (`| ^ & << >> + - * / % ~`) over
+//! decimal, `0x` and `0b` literals, or hexadecimal or binary unprefixed ones with
+//! [`eval_radix`]. Names are resolved against the fields of the register being
+//! edited, before hexadecimal literals made of letters like `FF`:
+//!
+//! - in a field input, a name is one of the field's enumerated values,
+//! - in a register input, a field name stands for the field mask, an enumerated
+//!   value name for the value shifted into its field, and `FIELD=value` for
+//!   `value` (possibly an enumerated value name) shifted into `FIELD`.
+
+use crate::model::Field;
+
+/// Names available to an expression.
+#[derive(Clone, Copy)]
+pub enum Scope<'a> {
+    Register(&'a [Field]),
+    Field(&'a Field),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Empty,
+    UnexpectedChar(char),
+    UnexpectedEnd,
+    UnexpectedToken(String),
+    UnknownName(String),
+    AmbiguousName(String),
+    DivisionByZero,
+    TooLarge,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Empty => f.write_str("empty expression"),
+            Error::UnexpectedChar(c) => write!(f, "unexpected character '{}'", c),
+            Error::UnexpectedEnd => f.write_str("unexpected end of expression"),
+            Error::UnexpectedToken(token) => write!(f, "unexpected '{}'", token),
+            Error::UnknownName(name) => write!(f, "unknown name '{}'", name),
+            Error::AmbiguousName(name) => write!(f, "'{}' is ambiguous, use FIELD={}", name, name),
+            Error::DivisionByZero => f.write_str("division by zero"),
+            Error::TooLarge => f.write_str("value too large"),
+        }
+    }
+}
+
+/// Evaluates `src` to a 16-bit value; negative results wrap around.
+pub fn eval(src: &str, scope: Scope) -> Result<u16, Error> {
+    eval_radix(src, scope, 10)
+}
+
+/// Evaluates `src` with unprefixed literals in `radix`.
+pub fn eval_radix(src: &str, scope: Scope, radix: u32) -> Result<u16, Error> {
+    let tokens = tokenize(src, radix)?;
+    if tokens.is_empty() {
+        return Err(Error::Empty);
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        scope,
+        radix,
+    };
+    let value = parser.or()?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(Error::UnexpectedToken(token.to_string()));
+    }
+    to_u16(value)
+}
+
+/// Whether `src` is a plain literal, in which case there's no point previewing it.
+pub fn is_literal(src: &str) -> bool {
+    matches!(tokenize(src, 10).as_deref(), Ok([Token::Number(_)]))
+}
+
+fn to_u16(value: i64) -> Result<u16, Error> {
+    if (-0x8000..=0xffff).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(Error::TooLarge)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(name) => f.write_str(name),
+            Token::Op(op) => f.write_str(op),
+        }
+    }
+}
+
+const OPS: [&str; 14] = [
+    "<<", ">>", "|", "^", "&", "+", "-", "*", "/", "%", "~", "(", ")", "=",
+];
+
+fn tokenize(src: &str, radix: u32) -> Result<Vec<Token>, Error> {
+    let mut tokens = vec![];
+    let mut rest = src.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let literal = rest[..end].replace('_', "");
+            let number = if let Some(hex) = literal.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(bin) = literal.strip_prefix("0b") {
+                i64::from_str_radix(bin, 2)
+            } else {
+                i64::from_str_radix(&literal, radix)
+            };
+            tokens.push(Token::Number(
+                number.map_err(|_| Error::UnexpectedToken(rest[..end].to_string()))?,
+            ));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(Error::UnexpectedChar(c));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    scope: Scope<'a>,
+    radix: u32,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.tokens.get(self.pos), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[&str],
+        next: fn(&mut Self) -> Result<i64, Error>,
+    ) -> Result<i64, Error> {
+        let mut value = next(self)?;
+        'outer: loop {
+            for op in ops {
+                if self.eat(op) {
+                    let rhs = next(self)?;
+                    value = match *op {
+                        "|" => value | rhs,
+                        "^" => value ^ rhs,
+                        "&" => value & rhs,
+                        "<<" => value.checked_shl(rhs as u32).ok_or(Error::TooLarge)?,
+                        ">>" => value.checked_shr(rhs as u32).unwrap_or(0),
+                        "+" => value.checked_add(rhs).ok_or(Error::TooLarge)?,
+                        "-" => value.checked_sub(rhs).ok_or(Error::TooLarge)?,
+                        "*" => value.checked_mul(rhs).ok_or(Error::TooLarge)?,
+                        "/" | "%" if rhs == 0 => return Err(Error::DivisionByZero),
+                        // Fails only for the overflowing i64::MIN / -1
+                        "/" => value.checked_div(rhs).ok_or(Error::TooLarge)?,
+                        "%" => value.checked_rem(rhs).ok_or(Error::TooLarge)?,
+                        _ => unreachable!(),
+                    };
+                    continue 'outer;
+                }
+            }
+            return Ok(value);
+        }
+    }
+
+    fn or(&mut self) -> Result<i64, Error> {
+        self.binary(&["|"], Self::xor)
+    }
+
+    fn xor(&mut self) -> Result<i64, Error> {
+        self.binary(&["^"], Self::and)
+    }
+
+    fn and(&mut self) -> Result<i64, Error> {
+        self.binary(&["&"], Self::shift)
+    }
+
+    fn shift(&mut self) -> Result<i64, Error> {
+        self.binary(&["<<", ">>"], Self::sum)
+    }
+
+    fn sum(&mut self) -> Result<i64, Error> {
+        self.binary(&["+", "-"], Self::product)
+    }
+
+    fn product(&mut self) -> Result<i64, Error> {
+        self.binary(&["*", "/", "%"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<i64, Error> {
+        if self.eat("~") {
+            Ok(!self.unary()?)
+        } else if self.eat("-") {
+            self.unary()?.checked_neg().ok_or(Error::TooLarge)
+        } else if self.eat("+") {
+            self.unary()
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, Error> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(n)) => Ok(n),
+            Some(Token::Op("(")) => {
+                let value = self.or()?;
+                if self.eat(")") {
+                    Ok(value)
+                } else {
+                    Err(self.unexpected())
+                }
+            }
+            Some(Token::Name(name)) => match self.name(&name) {
+                Err(Error::UnknownName(_)) if self.radix == 16 => {
+                    i64::from_str_radix(&name, 16).map_err(|_| Error::UnknownName(name))
+                }
+                result => result,
+            },
+            Some(token) => Err(Error::UnexpectedToken(token.to_string())),
+            None => Err(Error::UnexpectedEnd),
+        }
+    }
+
+    /// Value of `name` in the scope, with the `=value` following a field name.
+    fn name(&mut self, name: &str) -> Result<i64, Error> {
+        match self.scope {
+            Scope::Field(field) => enum_value(field, name),
+            Scope::Register(fields) => {
+                if self.eat("=") {
+                    let field = find_field(fields, name)?;
+                    let outer = std::mem::replace(&mut self.scope, Scope::Field(field));
+                    let value = self.unary();
+                    self.scope = outer;
+                    let value = to_u16(value?)?;
+                    if value > field.max_value() {
+                        return Err(Error::TooLarge);
+                    }
+                    Ok((value as i64) << field.offset)
+                } else {
+                    self.register_name(fields, name)
+                }
+            }
+        }
+    }
+
+    fn register_name(&self, fields: &[Field], name: &str) -> Result<i64, Error> {
+        if let Ok(field) = find_field(fields, name) {
+            return Ok((field.max_value() as i64) << field.offset);
+        }
+        let mut matches = fields.iter().filter_map(|field| {
+            enum_value(field, name)
+                .ok()
+                .map(|value| value << field.offset)
+        });
+        match (matches.next(), matches.next()) {
+            (Some(value), None) => Ok(value),
+            (Some(_), Some(_)) => Err(Error::AmbiguousName(name.to_string())),
+            _ => Err(Error::UnknownName(name.to_string())),
+        }
+    }
+
+    fn unexpected(&self) -> Error {
+        match self.tokens.get(self.pos) {
+            Some(token) => Error::UnexpectedToken(token.to_string()),
+            None => Error::UnexpectedEnd,
+        }
+    }
+}
+
+fn find_field<'a>(fields: &'a [Field], name: &str) -> Result<&'a Field, Error> {
+    fields
+        .iter()
+        .find(|field| field.name == name)
+        .ok_or_else(|| Error::UnknownName(name.to_string()))
+}
+
+fn enum_value(field: &Field, name: &str) -> Result<i64, Error> {
+    field
+        .enum_values
+        .iter()
+        .find(|val| val.name == name)
+        .map(|val| val.value as i64)
+        .ok_or_else(|| Error::UnknownName(name.to_string()))
+}
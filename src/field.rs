@@ -1,22 +1,23 @@
-use iced::widget::{button, column, row, text, text_input};
+use iced::widget::{button, checkbox, column, pick_list, row, text, text_input};
 use iced::{color, Color, Element, Renderer, Theme};
 
-use crate::reg16::EnumValue;
-use crate::reg16::ValState;
+use crate::format::{Format, InputError};
+use crate::model::{self, EnumValue, ValueError};
+use crate::reg16::{described, ValState};
 
 use crate::combo_box::{self, ComboBox};
+use crate::menu::Describe;
 
+/// Editing state of a [`model::Field`], which every method takes along.
 #[derive(Debug)]
 pub struct Field {
-    pub name: String,
     pub value_read: u16,
     pub value_write: u16,
     pub input_text: String,
-    pub description: Option<String>,
     pub state: ValState,
-    pub offset: u8,
-    pub width: u8,
-    pub enum_values: Vec<EnumValue>,
+    pub format: Format,
+    pub scale_text: String,
+    pub error: Option<InputError>,
     pub enum_combo_state: combo_box::State<EnumValue>,
     pub selected_enum: Option<EnumValue>,
     pub input_id: text_input::Id,
@@ -28,106 +29,263 @@ pub enum Message {
     InputChanged(String),
     WriteValueSubmit,
     ValSelected(EnumValue),
+    FlagToggled(u16, bool),
+    FormatSelected(Format),
+    ScaleFactorChanged(String),
+    ScaleUnitChanged(String),
+    /// Enter pressed in the scale factor or unit input.
+    ScaleSubmit,
 }
 
 impl Field {
-    pub fn value_from_reg(&self, reg: u16) -> u16 {
-        let mask = !(0xffffu16 << self.width);
-        (reg >> self.offset) & mask
+    pub fn new(model: &model::Field, format: Format) -> Self {
+        Field {
+            value_read: 0,
+            value_write: 0,
+            input_text: String::from("0x0000"),
+            state: ValState::None,
+            scale_text: match &format {
+                Format::Scaled(scale) => scale.factor.to_string(),
+                _ => String::from("1"),
+            },
+            format,
+            error: None,
+            enum_combo_state: combo_box::State::new(model.enum_values.clone()),
+            selected_enum: None,
+            input_id: text_input::Id::unique(),
+        }
+    }
+
+    /// Parses the input text with [`Format::parse_input`].
+    pub fn parse_input(&self, model: &model::Field) -> Result<u16, InputError> {
+        self.format
+            .parse_input(self.input_text.as_str(), model, self.value_read)
+    }
+
+    /// Value being edited, falling back to the write value while the input is
+    /// empty or invalid.
+    fn pending_value(&self, model: &model::Field) -> u16 {
+        self.parse_input(model).unwrap_or(self.value_write)
+    }
+
+    fn error_text(&self, model: &model::Field, error: &InputError) -> String {
+        match error {
+            InputError::Parse(err) => err.to_string(),
+            InputError::Expr(err) => err.to_string(),
+            InputError::Value(ValueError::TooWide) => format!(
+                "max {} for {} bits",
+                self.format.format(model.max_value(), model.width),
+                model.width
+            ),
+            InputError::Value(ValueError::OutOfRange { min, max }) => format!(
+                "allowed {}..={}",
+                self.format.format(*min, model.width),
+                self.format.format(*max, model.width)
+            ),
+            InputError::Value(err) => err.to_string(),
+        }
     }
 
-    pub fn value_reg_from_field(&self, reg: u16, field: u16) -> u16 {
-        let mask = (!(0xffffu16 << self.width)) << self.offset;
-        ((field << self.offset) & mask) | (reg & !mask)
+    /// Value of the input text when it's an expression rather than a plain number.
+    fn input_preview(&self, model: &model::Field) -> Option<Element<'_, Message, Theme, Renderer>> {
+        if self.error.is_some() || self.literal(model).is_some() {
+            return None;
+        }
+        let value = self.parse_input(model).ok()?;
+        Some(text(format!("= {}", self.format.format(value, model.width))).into())
     }
 
-    pub fn set_value_read_from_reg(&mut self, reg: u16) {
-        self.value_read = self.value_from_reg(reg);
+    /// Value of the input text when it's a plain number in the format rather
+    /// than an enumerated value name or an expression.
+    fn literal(&self, model: &model::Field) -> Option<u16> {
+        let src = self.input_text.as_str();
+        if model.enum_value_by_name(src.trim()).is_some() {
+            return None;
+        }
+        self.format.parse(src, model.width).ok()
     }
 
-    pub fn set_value_write_from_reg(&mut self, reg: u16) {
-        self.value_write = self.value_from_reg(reg);
+    /// Stops editing, discarding the text typed into the input.
+    pub fn cancel_edit(&mut self, model: &model::Field) {
+        self.state = ValState::Selected;
+        self.input_text = self.format.format(self.value_write, model.width);
+        self.error = None;
+    }
+
+    fn revalidate(&mut self, model: &model::Field) {
+        self.error = if self.input_text.trim().is_empty() {
+            None
+        } else {
+            self.parse_input(model).err()
+        };
     }
 
-    pub fn update(&mut self, message: Message) {
+    pub fn update(&mut self, model: &model::Field, message: Message) {
         match message {
             Message::Select(_) => match self.state {
                 ValState::None => self.state = ValState::Selected,
                 ValState::Selected => self.state = ValState::Editing,
                 ValState::Editing => (),
             },
-            Message::InputChanged(text) => self.input_text = text,
+            Message::InputChanged(text) => {
+                self.input_text = text;
+                self.revalidate(model);
+            }
             Message::ValSelected(val) => {
-                self.input_text = val.value.to_string();
+                self.input_text = self.format.format(val.value, model.width);
                 self.selected_enum = Some(val);
+                self.revalidate(model);
+            }
+            Message::FlagToggled(flag, checked) => {
+                let value = self.pending_value(model);
+                let value = if checked { value | flag } else { value & !flag };
+                self.input_text = self.format.format(value, model.width);
+                self.revalidate(model);
+            }
+            Message::FormatSelected(format) => {
+                if let Some(value) = self.literal(model) {
+                    self.input_text = format.format(value, model.width);
+                }
+                if let Format::Scaled(scale) = &format {
+                    self.scale_text = scale.factor.to_string();
+                }
+                self.format = format;
+                self.revalidate(model);
+            }
+            Message::ScaleFactorChanged(text) => {
+                if let (Format::Scaled(scale), Ok(factor)) = (&mut self.format, text.parse()) {
+                    scale.factor = factor;
+                }
+                self.scale_text = text;
             }
-            Message::WriteValueSubmit => (),
+            Message::ScaleUnitChanged(unit) => {
+                if let Format::Scaled(scale) = &mut self.format {
+                    scale.unit = unit;
+                }
+            }
+            Message::WriteValueSubmit | Message::ScaleSubmit => (),
         }
     }
 
-    pub fn view(&self) -> Element<Message, Theme, Renderer> {
-        let mut enum_value_read = None;
-        for val in self.enum_values.iter() {
-            if val.value == self.value_read {
-                enum_value_read = Some(val.name.as_str());
-            }
+    /// Checkbox per flag, checked when set in the value being edited.
+    fn flag_editor<'a>(&self, model: &'a model::Field) -> Element<'a, Message, Theme, Renderer> {
+        let value = self.pending_value(model);
+        column(
+            model
+                .enum_values
+                .iter()
+                .filter(|val| val.value != 0)
+                .map(|val| {
+                    let flag = val.value;
+                    described(
+                        checkbox(val.name.as_str(), value & flag != 0)
+                            .on_toggle(move |checked| Message::FlagToggled(flag, checked)),
+                        val.description.as_deref(),
+                    )
+                }),
+        )
+        .spacing(2)
+        .into()
+    }
+
+    /// Name of the enumerated value matching `value`, or of all flags set in it.
+    fn enum_value_name(
+        model: &model::Field,
+        value: u16,
+    ) -> Option<Element<'_, Message, Theme, Renderer>> {
+        if model.is_flags() {
+            return model.value_name(value).map(|names| text(names).into());
         }
-        let mut enum_value_write = None;
-        for val in self.enum_values.iter() {
-            if val.value == self.value_write {
-                enum_value_write = Some(val.name.as_str());
-            }
+        model
+            .enum_value(value)
+            .map(|val| described(val.name.as_str(), val.description.as_deref()))
+    }
+
+    fn format_selector(&self, model: &model::Field) -> Element<'_, Message, Theme, Renderer> {
+        let mut selector = row![pick_list(
+            self.format.options(model.width),
+            Some(self.format.clone()),
+            Message::FormatSelected
+        )
+        .padding([0, 5])]
+        .spacing(5);
+        if let Format::Scaled(scale) = &self.format {
+            selector = selector
+                .push(
+                    text_input("factor", self.scale_text.as_str())
+                        .width(60)
+                        .on_input(Message::ScaleFactorChanged)
+                        .on_submit(Message::ScaleSubmit),
+                )
+                .push(
+                    text_input("unit", scale.unit.as_str())
+                        .width(40)
+                        .on_input(Message::ScaleUnitChanged)
+                        .on_submit(Message::ScaleSubmit),
+                );
         }
-        let field_val_read = match self.width {
-            1 => format!("{}", self.value_read),
-            2..=4 => format!("0x{:01X}", self.value_read),
-            5..=8 => format!("0x{:02X}", self.value_read),
-            9..=12 => format!("0x{:03X}", self.value_read),
-            13..=16 => format!("0x{:04X}", self.value_read),
-            _ => unreachable!(),
-        };
-        let field_val_write = match self.width {
-            1 => format!("{}", self.value_write),
-            2..=4 => format!("0x{:01X}", self.value_write),
-            5..=8 => format!("0x{:02X}", self.value_write),
-            9..=12 => format!("0x{:03X}", self.value_write),
-            13..=16 => format!("0x{:04X}", self.value_write),
-            _ => unreachable!(),
+        selector.into()
+    }
+
+    pub fn view<'a>(&'a self, model: &'a model::Field) -> Element<'a, Message, Theme, Renderer> {
+        let enum_value_read = || Self::enum_value_name(model, self.value_read);
+        let enum_value_write = Self::enum_value_name(model, self.value_write);
+        let field_val_read = self.format.format(self.value_read, model.width);
+        let field_val_write = self.format.format(self.value_write, model.width);
+        let enum_editor = if model.is_flags() {
+            Some(self.flag_editor(model))
+        } else {
+            (!model.enum_values.is_empty()).then(|| {
+                ComboBox::new(
+                    &self.enum_combo_state,
+                    "enum value",
+                    self.selected_enum.as_ref(),
+                    Message::ValSelected,
+                )
+                .width(100)
+                .menu_width(360.0)
+                .into()
+            })
         };
-        let enum_combobox = ComboBox::new(
-            &self.enum_combo_state,
-            "placeholder",
-            self.selected_enum.as_ref(),
-            Message::ValSelected,
-        )
-        .width(100)
-        .on_input(Message::InputChanged);
 
         let field_val = match self.state {
-            ValState::Editing => row![
-                button(text(field_val_read.clone()))
-                    .style(button::text)
-                    .padding(0)
-                    .on_press(Message::Select(self.input_id.clone())),
-                ].push_maybe(enum_value_read).push("->").push(
+            ValState::Editing => row![button(text(field_val_read.clone()))
+                .style(button::text)
+                .padding(0)
+                .on_press(Message::Select(self.input_id.clone())),]
+            .push_maybe(enum_value_read())
+            .push("->")
+            .push(
                 column![
-                text_input(field_val_read.as_str(), self.input_text.as_str())
-                    .width(100)
-                    .on_input(Message::InputChanged)
-                    .on_submit(Message::WriteValueSubmit)
-                    .id(self.input_id.clone()),
-                enum_combobox,]),
+                    text_input(field_val_read.as_str(), self.input_text.as_str())
+                        .width(100)
+                        .on_input(Message::InputChanged)
+                        .on_submit(Message::WriteValueSubmit)
+                        .id(self.input_id.clone()),
+                ]
+                .push_maybe(
+                    self.error
+                        .as_ref()
+                        .map(|err| text(self.error_text(model, err)).color(color!(0xDD3333))),
+                )
+                .push_maybe(self.input_preview(model))
+                .push_maybe(enum_editor),
+            )
+            .push(self.format_selector(model)),
             ValState::None => row![button(text(field_val_read.clone()))
                 .style(button::text)
                 .padding(0)
-                .on_press(Message::Select(self.input_id.clone())),].push_maybe(enum_value_read),
+                .on_press(Message::Select(self.input_id.clone())),]
+            .push_maybe(enum_value_read()),
             ValState::Selected => row![button(text(field_val_read.clone()).color(Color::WHITE))
                 .style(|theme, status| {
                     button::text(theme, status).with_background(color!(0x3399FF))
                 })
                 .padding(0)
-                .on_press(Message::Select(self.input_id.clone()))].push_maybe(enum_value_read),
-        }.spacing(10);
+                .on_press(Message::Select(self.input_id.clone()))]
+            .push_maybe(enum_value_read()),
+        }
+        .spacing(10);
         let mut field_row = row![field_val,].spacing(10);
         if self.value_write != self.value_read {
             field_row = field_row.push("->");
@@ -138,9 +296,8 @@ impl Field {
     }
 }
 
-impl std::fmt::Display for EnumValue {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(format!("{} {}", self.value, self.name).as_str())
+impl Describe for EnumValue {
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
     }
 }
-
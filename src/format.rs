@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use crate::expr::{self, Scope};
+use crate::model::{Field, ValueError};
+
+/// File the per-field display formats are persisted to, one `PATH FORMAT` per line.
+pub const FORMATS_FILE: &str = "formats.txt";
+
+/// How a field value is displayed and how typed input is interpreted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    Hex,
+    Unsigned,
+    Signed,
+    Binary,
+    Scaled(Scale),
+}
+
+/// Linear scaling of the raw value into engineering units, `raw * factor unit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub factor: f64,
+    pub unit: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Invalid,
+    OutOfRange,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Invalid => f.write_str("invalid number"),
+            ParseError::OutOfRange => f.write_str("out of range"),
+        }
+    }
+}
+
+/// Why a value typed for a field can't be written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputError {
+    Parse(ParseError),
+    Expr(expr::Error),
+    Value(ValueError),
+}
+
+impl std::fmt::Display for InputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputError::Parse(err) => write!(f, "{}", err),
+            InputError::Expr(err) => write!(f, "{}", err),
+            InputError::Value(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Format {
+    pub fn default_for_width(width: u8) -> Format {
+        match width {
+            1 => Format::Unsigned,
+            _ => Format::Hex,
+        }
+    }
+
+    /// All formats offered in the selector for a field `width` bits wide,
+    /// keeping the scale of `self` if it has one. Zero-width fields have no
+    /// sign bit.
+    pub fn options(&self, width: u8) -> Vec<Format> {
+        let scale = match self {
+            Format::Scaled(scale) => scale.clone(),
+            _ => Scale {
+                factor: 1.0,
+                unit: String::new(),
+            },
+        };
+        let mut options = vec![
+            Format::Hex,
+            Format::Unsigned,
+            Format::Signed,
+            Format::Binary,
+            Format::Scaled(scale),
+        ];
+        if width == 0 {
+            options.retain(|format| *format != Format::Signed);
+        }
+        options
+    }
+
+    pub fn format(&self, value: u16, width: u8) -> String {
+        match self {
+            Format::Hex => format!("0x{:0w$X}", value, w = (width as usize).div_ceil(4)),
+            Format::Unsigned => format!("{}", value),
+            Format::Signed => format!("{}", sign_extend(value, width)),
+            Format::Binary => format!("0b{:0w$b}", value, w = width as usize),
+            Format::Scaled(scale) => format!("{} {}", value as f64 * scale.factor, scale.unit),
+        }
+    }
+
+    /// Radix of the unprefixed literals of integer formats, also used for the
+    /// literals of expressions typed in them.
+    pub fn radix(&self) -> Option<u32> {
+        match self {
+            Format::Hex => Some(16),
+            Format::Unsigned => Some(10),
+            Format::Binary => Some(2),
+            Format::Signed | Format::Scaled(_) => None,
+        }
+    }
+
+    /// Parses a value typed for `field` in this format, falling back to
+    /// evaluating it as an expression over the enumerated value names, and
+    /// checks it against the field width and its write constraint, `read` being
+    /// the value last read from the field.
+    ///
+    /// In integer formats the whole input is an expression with literals in the
+    /// format's radix, enumerated value names taking precedence over
+    /// hexadecimal literals.
+    pub fn parse_input(&self, src: &str, field: &Field, read: u16) -> Result<u16, InputError> {
+        let value = match self.radix() {
+            Some(radix) => {
+                expr::eval_radix(src, Scope::Field(field), radix).map_err(InputError::Expr)?
+            }
+            None => match self.parse(src, field.width) {
+                Ok(value) => value,
+                Err(ParseError::OutOfRange) => {
+                    return Err(InputError::Parse(ParseError::OutOfRange))
+                }
+                Err(ParseError::Invalid) => {
+                    expr::eval(src, Scope::Field(field)).map_err(InputError::Expr)?
+                }
+            },
+        };
+        field.validate(value, read).map_err(InputError::Value)?;
+        Ok(value)
+    }
+
+    /// Parses `src` written in this format into a raw value.
+    ///
+    /// Literals with an explicit `0x` or `0b` prefix are accepted in every format.
+    pub fn parse(&self, src: &str, width: u8) -> Result<u16, ParseError> {
+        let src = src.trim().trim_start_matches('+');
+        if let Some(hex) = src.strip_prefix("0x") {
+            return parse_radix(hex, 16);
+        }
+        if let Some(bin) = src.strip_prefix("0b") {
+            return parse_radix(bin, 2);
+        }
+        match self {
+            Format::Hex => parse_radix(src, 16),
+            Format::Unsigned => parse_radix(src, 10),
+            Format::Binary => parse_radix(src, 2),
+            Format::Signed => {
+                let value = src.parse::<i32>().map_err(|_| ParseError::Invalid)?;
+                let Some(sign_bit) = width.checked_sub(1) else {
+                    return if value == 0 {
+                        Ok(0)
+                    } else {
+                        Err(ParseError::OutOfRange)
+                    };
+                };
+                let half = 1i32 << sign_bit;
+                if value < -half || value >= half {
+                    return Err(ParseError::OutOfRange);
+                }
+                Ok((value as u16) & !(0xffffu16.checked_shl(width as u32).unwrap_or(0)))
+            }
+            Format::Scaled(scale) => {
+                let src = src.strip_suffix(scale.unit.as_str()).unwrap_or(src);
+                let value = src.trim().parse::<f64>().map_err(|_| ParseError::Invalid)?;
+                let raw = (value / scale.factor).round();
+                if !(0.0..=u16::MAX as f64).contains(&raw) {
+                    return Err(ParseError::OutOfRange);
+                }
+                Ok(raw as u16)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Hex => f.write_str("hex"),
+            Format::Unsigned => f.write_str("dec"),
+            Format::Signed => f.write_str("signed"),
+            Format::Binary => f.write_str("bin"),
+            Format::Scaled(_) => f.write_str("scaled"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = ParseError;
+
+    /// Parses the persisted form, the [`Display`](std::fmt::Display) name followed
+    /// by `:factor:unit` for scaled formats.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("scaled", scale)) => {
+                let (factor, unit) = scale.split_once(':').unwrap_or((scale, ""));
+                Ok(Format::Scaled(Scale {
+                    factor: factor.parse().map_err(|_| ParseError::Invalid)?,
+                    unit: unit.to_string(),
+                }))
+            }
+            Some(_) => Err(ParseError::Invalid),
+            None => match s {
+                "hex" => Ok(Format::Hex),
+                "dec" => Ok(Format::Unsigned),
+                "signed" => Ok(Format::Signed),
+                "bin" => Ok(Format::Binary),
+                _ => Err(ParseError::Invalid),
+            },
+        }
+    }
+}
+
+fn to_persisted(format: &Format) -> String {
+    match format {
+        Format::Scaled(scale) => format!("scaled:{}:{}", scale.factor, scale.unit),
+        _ => format.to_string(),
+    }
+}
+
+fn parse_radix(src: &str, radix: u32) -> Result<u16, ParseError> {
+    let src = src.replace('_', "");
+    u16::from_str_radix(&src, radix).map_err(|err| match err.kind() {
+        std::num::IntErrorKind::PosOverflow => ParseError::OutOfRange,
+        _ => ParseError::Invalid,
+    })
+}
+
+fn sign_extend(value: u16, width: u8) -> i32 {
+    if width == 0 {
+        return 0;
+    }
+    let shift = 32 - width as u32;
+    ((value as i32) << shift) >> shift
+}
+
+/// Loads the formats persisted by [`save`], keyed by `PERIPH.REG.FIELD` path.
+pub fn load(path: &str) -> HashMap<String, Format> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let (path, format) = line.split_once(' ')?;
+            Some((path.to_string(), format.parse().ok()?))
+        })
+        .collect()
+}
+
+pub fn save<'a>(
+    path: &str,
+    formats: impl IntoIterator<Item = (String, &'a Format)>,
+) -> io::Result<()> {
+    let content: String = formats
+        .into_iter()
+        .map(|(path, format)| format!("{} {}\n", path, to_persisted(format)))
+        .collect();
+    fs::write(path, content)
+}
@@ -0,0 +1,228 @@
+//! Import of register maps from IP-XACT (IEEE 1685) component descriptions, in
+//! the 1.5/2009 `spirit` as well as the 2014/2022 `ipxact` schemas.
+//!
+//! Each address block of the memory maps becomes a peripheral at its base
+//! address. Register files are flattened into it, prefixing the register names
+//! like `FILE_REG`, and register arrays are expanded with the index appended.
+
+use roxmltree::{Document, Node};
+
+use crate::model::{
+    parse_number, Access, Device, EnumValue, Field, ParseError, Peripheral, Register, REG_BITS,
+};
+
+/// Whether the XML document is an IP-XACT component rather than an SVD device.
+pub fn is_component(xml: &str) -> bool {
+    Document::parse(xml).is_ok_and(|doc| doc.root_element().tag_name().name() == "component")
+}
+
+/// Parses an IP-XACT component into the device model.
+pub fn parse(xml: &str) -> Result<Device, ParseError> {
+    let doc = Document::parse(xml)?;
+    let component = doc.root_element();
+    if component.tag_name().name() != "component" {
+        return Err("not an IP-XACT component".into());
+    }
+    let mut peripherals = vec![];
+    for map in children(component, "memoryMaps").flat_map(|maps| children(maps, "memoryMap")) {
+        for block in children(map, "addressBlock") {
+            let base_address = number(block, "baseAddress")?.unwrap_or(0) as u32;
+            let mut registers = vec![];
+            collect_registers(block, base_address, "", access(block), &mut registers)?;
+            peripherals.push(Peripheral {
+                name: name(block)?,
+                description: text(block, "description"),
+                base_address,
+                registers,
+                clusters: vec![],
+            });
+        }
+    }
+    Ok(Device {
+        name: name(component)?,
+        peripherals,
+    })
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// Text of the child element on a single line.
+fn text(node: Node, name: &'static str) -> Option<String> {
+    let text = child(node, name)?.text()?;
+    Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn name(node: Node) -> Result<String, ParseError> {
+    text(node, "name").ok_or_else(|| format!("{} without a name", node.tag_name().name()).into())
+}
+
+fn number(node: Node, name: &'static str) -> Result<Option<u64>, ParseError> {
+    match text(node, name) {
+        Some(text) => match parse_number(&text) {
+            Some(number) => Ok(Some(number)),
+            None => Err(format!("invalid {} '{}'", name, text).into()),
+        },
+        None => Ok(None),
+    }
+}
+
+fn access(node: Node) -> Option<Access> {
+    match text(node, "access")?.as_str() {
+        "read-only" => Some(Access::ReadOnly),
+        "write-only" | "writeOnce" => Some(Access::WriteOnly),
+        _ => Some(Access::ReadWrite),
+    }
+}
+
+/// Reset value of a register or field, from `reset` or the first of `resets`.
+fn reset(node: Node) -> Result<Option<u64>, ParseError> {
+    let reset = child(node, "reset").or_else(|| child(node, "resets")?.first_element_child());
+    match reset {
+        Some(reset) => number(reset, "value"),
+        None => Ok(None),
+    }
+}
+
+/// Adds the registers of an address block or register file at `address`.
+fn collect_registers(
+    parent: Node,
+    address: u32,
+    prefix: &str,
+    parent_access: Option<Access>,
+    registers: &mut Vec<Register>,
+) -> Result<(), ParseError> {
+    for node in parent.children().filter(Node::is_element) {
+        let is_file = match node.tag_name().name() {
+            "register" => false,
+            "registerFile" => true,
+            _ => continue,
+        };
+        let name = format!("{}{}", prefix, name(node)?);
+        let offset = number(node, "addressOffset")?.unwrap_or(0);
+        let access = access(node).or(parent_access);
+
+        // `dim` is directly in the element up to 2014, in `array` since 2022
+        let array = child(node, "array");
+        let mut dims = vec![];
+        for dim in children(node, "dim").chain(array.into_iter().flat_map(|a| children(a, "dim"))) {
+            let text = dim.text().unwrap_or_default().trim();
+            dims.push(parse_number(text).ok_or_else(|| format!("invalid dim '{}'", text))?);
+        }
+        let stride = match array
+            .map(|array| number(array, "stride"))
+            .transpose()?
+            .flatten()
+        {
+            Some(stride) => stride,
+            None if is_file => number(node, "range")?.unwrap_or(0),
+            None => number(node, "size")?.unwrap_or(32) / 8,
+        };
+
+        let count: u64 = dims.iter().product();
+        for i in 0..count {
+            let name = if dims.is_empty() {
+                name.clone()
+            } else {
+                format!("{}{}", name, i)
+            };
+            let address = address + (offset + i * stride) as u32;
+            if is_file {
+                collect_registers(node, address, &format!("{}_", name), access, registers)?;
+            } else {
+                registers.push(register(node, name, address, access)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn register(
+    node: Node,
+    name: String,
+    address: u32,
+    reg_access: Option<Access>,
+) -> Result<Register, ParseError> {
+    if let Some(size) = number(node, "size")? {
+        if size > REG_BITS as u64 {
+            return Err(format!(
+                "register {}: size {} wider than {} bits",
+                name, size, REG_BITS
+            )
+            .into());
+        }
+    }
+    let mut reset_value = match reset(node)? {
+        Some(reset) => u16::try_from(reset)
+            .map_err(|_| format!("register {}: reset 0x{:X} too wide", name, reset))?,
+        None => 0,
+    };
+    let mut read_side_effects = child(node, "readAction").is_some();
+    let mut fields = vec![];
+    for elem in children(node, "field") {
+        let field_name = self::name(elem)?;
+        let offset = number(elem, "bitOffset")?.unwrap_or(0);
+        let width = number(elem, "bitWidth")?.unwrap_or(1);
+        if width == 0 || offset + width > REG_BITS as u64 {
+            return Err(format!(
+                "field {}.{}: bits {}..{} beyond the register width",
+                name,
+                field_name,
+                offset,
+                offset + width
+            )
+            .into());
+        }
+        let mut field = Field {
+            name: field_name,
+            description: text(elem, "description"),
+            offset: offset as u8,
+            width: width as u8,
+            access: access(elem).or(reg_access).unwrap_or_default(),
+            write_constraint: None,
+            enum_values: vec![],
+        };
+        let fits = |what: &str, value: u64| match u16::try_from(value) {
+            Ok(value) if value <= field.max_value() => Ok(value),
+            _ => Err(ParseError::from(format!(
+                "field {}.{}: {} 0x{:X} too wide",
+                name, field.name, what, value
+            ))),
+        };
+        let mut enum_values = vec![];
+        for value in children(elem, "enumeratedValues")
+            .flat_map(|values| children(values, "enumeratedValue"))
+        {
+            let number = number(value, "value")?.ok_or("enumeratedValue without a value")?;
+            enum_values.push(EnumValue {
+                name: self::name(value)?,
+                description: text(value, "description"),
+                value: fits("value", number)?,
+            });
+        }
+        if let Some(reset) = reset(elem)? {
+            reset_value = field.encode(reset_value, fits("reset", reset)?);
+        }
+        field.enum_values = enum_values;
+        read_side_effects |= child(elem, "readAction").is_some();
+        fields.push(field);
+    }
+    Ok(Register {
+        name,
+        description: text(node, "description"),
+        address,
+        reset_value,
+        read_side_effects,
+        fields,
+        cluster: None,
+    })
+}
@@ -0,0 +1,45 @@
+//! Register tree widget for iced applications, built from an SVD description.
+//!
+//! The [`model`] of the device registers doesn't depend on iced and is available
+//! without the default `gui` feature.
+//!
+//! Embed a [`RegTree`] in your application state, forward its messages to
+//! [`RegTree::update`] and carry out the reads and writes it returns as
+//! [`reg_tree::Action`]s, e.g. with the [`backend`] helpers.
+
+pub mod backend;
+pub mod bulk_read;
+pub mod cli;
+#[cfg(feature = "gui")]
+pub mod combo_box;
+pub mod doc;
+pub mod export;
+pub mod expr;
+#[cfg(feature = "gui")]
+pub mod field;
+pub mod format;
+pub mod ipxact;
+#[cfg(feature = "gui")]
+pub mod macro_panel;
+pub mod macros;
+#[cfg(feature = "gui")]
+pub mod menu;
+pub mod model;
+#[cfg(feature = "gui")]
+pub mod nav;
+pub mod patch;
+#[cfg(feature = "gui")]
+pub mod peripheral;
+pub mod rdl;
+#[cfg(feature = "gui")]
+pub mod reg16;
+#[cfg(feature = "gui")]
+pub mod reg_tree;
+pub mod regdesc;
+pub mod repl;
+pub mod script;
+#[cfg(feature = "gui")]
+pub mod script_panel;
+
+#[cfg(feature = "gui")]
+pub use reg_tree::RegTree;
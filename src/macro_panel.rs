@@ -0,0 +1,193 @@
+//! Controls recording the committed writes as a [macro](crate::macros) and
+//! playing it back.
+//!
+//! Like the tree, the panel doesn't access the device itself: the application
+//! reports writes with [`MacroPanel::record`], executes the steps handed out as
+//! [`Action::Play`] and answers them with [`Message::StepDone`].
+
+use iced::widget::{button, row, text, text_input};
+use iced::{Element, Renderer, Theme};
+
+use crate::bulk_read::Target;
+use crate::macros::{Recorder, Step};
+
+pub struct MacroPanel {
+    pub steps: Vec<Step>,
+    pub recorder: Option<Recorder>,
+    playback: Option<Playback>,
+    /// Factor the recorded waits are shortened by.
+    pub speed: f64,
+    speed_input: String,
+    status: String,
+}
+
+/// Position of the playback in the steps.
+struct Playback {
+    next: usize,
+    /// Pause after each write instead of running to the end.
+    stepping: bool,
+    /// A step is being executed.
+    busy: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    ToggleRecord,
+    Play,
+    Step,
+    Stop,
+    SpeedChanged(String),
+    StepDone(Result<Option<(Target, u16)>, String>),
+}
+
+pub enum Action {
+    None,
+    /// Execute the step and answer with [`Message::StepDone`].
+    Play(Step),
+    /// Recording stopped, the application may persist the steps.
+    Recorded,
+}
+
+impl MacroPanel {
+    pub fn new(steps: Vec<Step>) -> Self {
+        MacroPanel {
+            status: format!("{} steps", steps.len()),
+            steps,
+            recorder: None,
+            playback: None,
+            speed: 1.0,
+            speed_input: String::from("1"),
+        }
+    }
+
+    /// Records a write the backend carried out, if recording.
+    pub fn record(&mut self, path: String, value: u16) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(path, value);
+            self.status = format!("Recording, {} steps", recorder.steps.len());
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::ToggleRecord => match self.recorder.take() {
+                Some(recorder) => {
+                    self.steps = recorder.steps;
+                    self.status = format!("Recorded {} steps", self.steps.len());
+                    Action::Recorded
+                }
+                None => {
+                    self.playback = None;
+                    self.recorder = Some(Recorder::default());
+                    self.status = String::from("Recording");
+                    Action::None
+                }
+            },
+            Message::Play | Message::Step => {
+                let stepping = matches!(message, Message::Step);
+                match self.playback.as_mut() {
+                    Some(playback) if playback.busy => {
+                        // Pause or run on once the current step is done
+                        playback.stepping = stepping;
+                        Action::None
+                    }
+                    Some(playback) => {
+                        playback.stepping = stepping;
+                        self.next_step()
+                    }
+                    None => {
+                        self.playback = Some(Playback {
+                            next: 0,
+                            stepping,
+                            busy: false,
+                        });
+                        self.next_step()
+                    }
+                }
+            }
+            Message::Stop => {
+                self.playback = None;
+                self.status = String::from("Stopped");
+                Action::None
+            }
+            Message::SpeedChanged(val) => {
+                if let Ok(speed) = val.trim().parse::<f64>() {
+                    if speed.is_finite() && speed > 0.0 {
+                        self.speed = speed;
+                    }
+                }
+                self.speed_input = val;
+                Action::None
+            }
+            Message::StepDone(result) => {
+                let Some(playback) = self.playback.as_mut() else {
+                    return Action::None;
+                };
+                playback.busy = false;
+                match result {
+                    Err(err) => {
+                        self.status = format!("Step {} failed: {}", playback.next, err);
+                        self.playback = None;
+                        Action::None
+                    }
+                    Ok(Some(_)) if playback.stepping => {
+                        self.status = format!("Step {}/{}", playback.next, self.steps.len());
+                        if playback.next == self.steps.len() {
+                            self.playback = None;
+                        }
+                        Action::None
+                    }
+                    Ok(_) => self.next_step(),
+                }
+            }
+        }
+    }
+
+    /// Hands out the next step, skipping waits when stepping through.
+    fn next_step(&mut self) -> Action {
+        let Some(playback) = self.playback.as_mut() else {
+            return Action::None;
+        };
+        while let Some(step) = self.steps.get(playback.next) {
+            playback.next += 1;
+            if playback.stepping && matches!(step, Step::Sleep(_)) {
+                continue;
+            }
+            playback.busy = true;
+            self.status = format!("Step {}/{}", playback.next, self.steps.len());
+            return Action::Play(step.clone());
+        }
+        self.playback = None;
+        self.status = String::from("Finished");
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message, Theme, Renderer> {
+        let recording = self.recorder.is_some();
+        row![
+            button(text(if recording {
+                "Stop recording"
+            } else {
+                "Record"
+            }))
+            .padding([0, 5])
+            .on_press(Message::ToggleRecord),
+            button(text("Play"))
+                .padding([0, 5])
+                .on_press_maybe((!recording && !self.steps.is_empty()).then_some(Message::Play)),
+            button(text("Step"))
+                .padding([0, 5])
+                .on_press_maybe((!recording && !self.steps.is_empty()).then_some(Message::Step)),
+            button(text("Stop"))
+                .padding([0, 5])
+                .on_press_maybe(self.playback.is_some().then_some(Message::Stop)),
+            text("speed"),
+            text_input("", self.speed_input.as_str())
+                .width(60)
+                .on_input(Message::SpeedChanged),
+            text(self.status.as_str()),
+        ]
+        .spacing(10)
+        .into()
+    }
+}
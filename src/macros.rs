@@ -0,0 +1,208 @@
+//! Macros of register writes recorded in the GUI, replayed step by step.
+//!
+//! A macro is kept as text, one step per line, `#` starting a comment:
+//!
+//! ```text
+//! sleep 1200
+//! write TIMER0.CR=0x0013   # EN=Enable MODE=Single_MATCH
+//! write TIMER0.CR.EN=Disable
+//! ```
+//!
+//! Values are [expressions](crate::expr), field writes are replayed by
+//! read-modify-write.
+
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_timer::Delay;
+
+use crate::backend::{self, Backend};
+use crate::bulk_read::Target;
+use crate::expr::{self, Scope};
+use crate::format::Format;
+use crate::model::Device;
+
+/// File the recorded macro is persisted to.
+pub const MACRO_FILE: &str = "macro.txt";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Sleep(Duration),
+    /// Write the expression to a `PERIPH.REG` or `PERIPH.REG.FIELD` path.
+    Write {
+        path: String,
+        value: String,
+    },
+}
+
+/// Syntax error on a line of a macro.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Parses the text of a macro.
+pub fn parse(src: &str) -> Result<Vec<Step>, ParseError> {
+    let mut steps = vec![];
+    for (idx, line) in src.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(step, _)| step).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| ParseError {
+            line: idx + 1,
+            message,
+        };
+        let (command, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        steps.push(match command {
+            "sleep" => Step::Sleep(Duration::from_millis(
+                arg.trim()
+                    .parse()
+                    .map_err(|_| error(format!("invalid sleep '{}'", arg.trim())))?,
+            )),
+            "write" => {
+                let (path, value) = arg
+                    .split_once('=')
+                    .ok_or_else(|| error(format!("expected PATH=VALUE, got '{}'", arg.trim())))?;
+                Step::Write {
+                    path: path.trim().to_string(),
+                    value: value.trim().to_string(),
+                }
+            }
+            _ => return Err(error(format!("unknown step '{}'", command))),
+        });
+    }
+    Ok(steps)
+}
+
+/// Text of the macro, commenting register writes with the fields they set.
+pub fn to_text(steps: &[Step], device: &Device) -> String {
+    steps
+        .iter()
+        .map(|step| match step {
+            Step::Sleep(duration) => format!("sleep {}\n", duration.as_millis()),
+            Step::Write { path, value } => {
+                let fields = device.register(path).and_then(|(_, reg)| {
+                    let value = expr::eval(value, Scope::Register(&reg.fields)).ok()?;
+                    let fields: Vec<String> = reg
+                        .decode(value)
+                        .map(|(field, val)| {
+                            let name = field.value_name(val).unwrap_or_else(|| {
+                                Format::default_for_width(field.width).format(val, field.width)
+                            });
+                            format!("{}={}", field.name, name)
+                        })
+                        .collect();
+                    Some(fields.join(" "))
+                });
+                match fields {
+                    Some(fields) => format!("write {}={}   # {}\n", path, value, fields),
+                    None => format!("write {}={}\n", path, value),
+                }
+            }
+        })
+        .collect()
+}
+
+pub fn load(path: &str) -> io::Result<Vec<Step>> {
+    parse(&fs::read_to_string(path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+}
+
+pub fn save(path: &str, steps: &[Step], device: &Device) -> io::Result<()> {
+    fs::write(path, to_text(steps, device))
+}
+
+/// Collects committed writes with the time between them.
+pub struct Recorder {
+    pub steps: Vec<Step>,
+    last: Instant,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder {
+            steps: vec![],
+            last: Instant::now(),
+        }
+    }
+}
+
+impl Recorder {
+    /// Records a write of `value` to the `PERIPH.REG` path, after a sleep for the
+    /// time since the previous one.
+    pub fn record(&mut self, path: String, value: u16) {
+        let now = Instant::now();
+        let wait = Duration::from_millis((now - self.last).as_millis() as u64);
+        if !wait.is_zero() {
+            self.steps.push(Step::Sleep(wait));
+        }
+        self.steps.push(Step::Write {
+            path,
+            value: Format::Hex.format(value, 16),
+        });
+        self.last = now;
+    }
+}
+
+/// Executes a step, sleeping `speed` times faster than recorded. Returns the
+/// register written and its new value.
+pub async fn play(
+    step: Step,
+    device: Arc<Device>,
+    backend: Arc<dyn Backend>,
+    config: backend::Config,
+    speed: f64,
+) -> Result<Option<(Target, u16)>, String> {
+    let (path, src) = match step {
+        Step::Sleep(duration) => {
+            Delay::new(duration.div_f64(speed)).await;
+            return Ok(None);
+        }
+        Step::Write { path, value } => (path, value),
+    };
+    let unknown = || format!("unknown register or field '{}'", path);
+    let (reg_path, field) = match device.register_position(&path) {
+        Some(_) => (path.as_str(), None),
+        None => {
+            let (reg_path, field) = path.rsplit_once('.').ok_or_else(unknown)?;
+            (reg_path, Some(field))
+        }
+    };
+    let (p, r) = device.register_position(reg_path).ok_or_else(unknown)?;
+    let reg = &device.peripherals[p].registers[r];
+    let target = Target {
+        periph: p,
+        reg: r,
+        address: reg.address,
+    };
+    let value = match field {
+        Some(name) => {
+            let field = reg.field(name).ok_or_else(unknown)?;
+            let value = expr::eval(&src, Scope::Field(field))
+                .map_err(|err| format!("{}: {}", path, err))?;
+            let read = backend::read(backend.clone(), config, target.address)
+                .await
+                .map_err(|err| format!("reading {} failed: {}", reg_path, err))?;
+            field
+                .validate(value, field.decode(read))
+                .map_err(|err| format!("{}: {}", path, err))?;
+            field.encode(read, value)
+        }
+        None => expr::eval(&src, Scope::Register(&reg.fields))
+            .map_err(|err| format!("{}: {}", path, err))?,
+    };
+    backend::write(backend, config, target.address, value)
+        .await
+        .map_err(|err| format!("writing {} failed: {}", reg_path, err))?;
+    Ok(Some((target, value)))
+}
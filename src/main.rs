@@ -1,141 +1,528 @@
-use svd_parser as svd;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 
-use std::fs::File;
-use std::io::Read;
+use iced::widget::{button, column, progress_bar, row, text, text_input, Column};
+use iced::{Element, Font, Subscription, Task};
 
-use iced::widget::{column, text_input, Column};
-use iced::{Font, Task};
-
-pub mod combo_box;
-pub mod field;
-pub mod reg16;
-
-use field::Field;
-use reg16::{EnumValue, Reg16, ValState};
+use iced_reg_tree::backend::{self, Backend};
+use iced_reg_tree::bulk_read::{BulkRead, Target};
+use iced_reg_tree::cli;
+use iced_reg_tree::doc::{self, DocFormat};
+use iced_reg_tree::export;
+use iced_reg_tree::format;
+use iced_reg_tree::ipxact;
+use iced_reg_tree::macro_panel::{self, MacroPanel};
+use iced_reg_tree::macros;
+use iced_reg_tree::model::{self, Device};
+use iced_reg_tree::patch;
+use iced_reg_tree::rdl;
+use iced_reg_tree::reg16::IoState;
+use iced_reg_tree::reg_tree::{self, RegTree};
+use iced_reg_tree::regdesc;
+use iced_reg_tree::repl;
+use iced_reg_tree::script::{self, Script};
+use iced_reg_tree::script_panel::{self, ScriptPanel};
 
 pub fn main() -> iced::Result {
+    let args = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.mode != cli::Mode::Gui {
+        std::process::exit(run_headless(&args));
+    }
     iced::application("Iced Reg Tree", App::update, App::view)
+        .subscription(App::subscription)
         .default_font(Font::MONOSPACE)
-        .run_with(App::init)
+        .run_with(move || App::init(&args.svd, args.patch.as_deref()))
+}
+
+/// Runs a command line command or the console without the GUI, returning the
+/// exit code.
+fn run_headless(args: &cli::Args) -> i32 {
+    let device = match load_device(&args.svd, args.patch.as_deref()) {
+        Ok(device) => device,
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", args.svd, err);
+            return 1;
+        }
+    };
+    let session = cli::Session {
+        backend: Arc::new(simulator(&device)),
+        device,
+        config: backend::Config::default(),
+        formats: format::load(format::FORMATS_FILE),
+    };
+    let result: Result<(), Box<dyn std::error::Error>> = match &args.mode {
+        cli::Mode::Command(command) => session
+            .run(command)
+            .map(|output| print!("{}", output))
+            .map_err(Into::into),
+        _ => repl::run(&session).map_err(Into::into),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+/// Loads the register description, SystemRDL for `.rdl` files, the lightweight
+/// format for YAML and TOML ones, and IP-XACT or SVD depending on the XML root
+/// element otherwise. The `patch` file is applied on top.
+fn load_device(path: &str, patch: Option<&str>) -> Result<Device, model::ParseError> {
+    let src = std::fs::read_to_string(path)?;
+    let mut device = match std::path::Path::new(path).extension() {
+        Some(ext) if ext == "rdl" => rdl::parse(&src)?,
+        Some(ext) if ext == "yaml" || ext == "yml" => regdesc::parse_yaml(&src)?,
+        Some(ext) if ext == "toml" => regdesc::parse_toml(&src)?,
+        _ if ipxact::is_component(&src) => ipxact::parse(&src)?,
+        _ => Device::parse_svd(&src)?,
+    };
+    if let Some(patch) = patch {
+        patch::load(patch, &mut device).map_err(|err| format!("{}: {}", patch, err))?;
+    }
+    Ok(device)
+}
+
+/// Simulated target with every register at its reset value.
+fn simulator(device: &Device) -> backend::Simulator {
+    let simulator = backend::Simulator::new(Duration::from_millis(50));
+    for reg in device
+        .peripherals
+        .iter()
+        .flat_map(|periph| &periph.registers)
+    {
+        simulator.preset(reg.address, reg.reset_value);
+    }
+    simulator
 }
 
 struct App {
-    regs: Vec<Reg16>,
+    tree: RegTree,
+    backend: Arc<dyn Backend>,
+    config: backend::Config,
+    timeout_input: String,
+    retries_input: String,
+    /// Bulk read waiting for the user to decide about registers with read side effects.
+    unconfirmed_read: Option<Vec<usize>>,
+    bulk_read: Option<BulkRead>,
+    /// Model the scripts run against.
+    device: Arc<Device>,
+    script_panel: ScriptPanel,
+    show_script: bool,
+    macro_panel: MacroPanel,
 }
 
 #[derive(Debug, Clone)]
 enum Message {
-    Reg(usize, reg16::Message),
+    Tree(reg_tree::Message),
+    TimeoutChanged(String),
+    RetriesChanged(String),
+    ReadAll,
+    ConfirmRead(bool),
+    CancelRead,
+    BulkReadDone(Result<Vec<u16>, backend::Error>),
+    DismissSummary,
+    ExportC,
+    ExportRust,
+    ExportDoc,
+    ToggleScript,
+    Script(script_panel::Message),
+    Macro(macro_panel::Message),
+    /// A write requested by the tree finished, recorded into the macro if it
+    /// went through.
+    WriteDone(Target, u16, Result<(), backend::Error>),
 }
 
 impl App {
-    fn init() -> (App, Task<Message>) {
-        let xml = &mut String::new();
-        let _ = File::open("example.svd").unwrap().read_to_string(xml);
-        let device = svd::parse(xml).unwrap();
-        let mut regs = vec![];
-
-        for periph in device.peripherals {
-            for reg in periph.all_registers() {
-                let mut fields = vec![];
-                for field in reg.fields() {
-                    let mut enum_values = vec![];
-                    for svd_enum_values in field.enumerated_values.iter() {
-                        for svd_enum_value in svd_enum_values.values.iter() {
-                            if let Some(val) = svd_enum_value.value {
-                                enum_values.push(EnumValue {
-                                    name: svd_enum_value.name.clone(),
-                                    description: svd_enum_value.description.clone(),
-                                    value: val as u16,
-                                })
-                            }
-                        }
-                    }
-                    fields.push(Field {
-                        name: field.name.clone(),
-                        description: field.description.clone(),
-                        value_read: 0,
-                        value_write: 0,
-                        input_text: String::from("0x0000"),
-                        state: ValState::None,
-                        offset: field.bit_range.offset as u8,
-                        width: field.bit_range.width as u8,
-                        enum_values: enum_values.clone(),
-                        input_id: text_input::Id::unique(),
-                        enum_combo_state: combo_box::State::new(enum_values.clone()),
-                        selected_enum: None,
-                    });
+    fn init(svd: &str, patch: Option<&str>) -> (App, Task<Message>) {
+        let device = load_device(svd, patch).unwrap();
+        let simulator = simulator(&device);
+        let formats = format::load(format::FORMATS_FILE);
+        let tree = RegTree::from_device(device.clone(), &formats);
+        let script = std::fs::read_to_string(script::SCRIPT_FILE).unwrap_or_default();
+        let steps = match macros::load(macros::MACRO_FILE) {
+            Ok(steps) => steps,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Failed to load {}: {}", macros::MACRO_FILE, err);
                 }
-                regs.push(Reg16 {
-                    name: reg.name.clone(),
-                    description: reg.description.clone(),
-                    expanded: false,
-                    state: ValState::None,
-                    value_read: 0,
-                    value_write: 0,
-                    input_text: String::from("0x0000"),
-                    fields,
-                    input_id: text_input::Id::unique(),
-                })
+                vec![]
             }
-        }
+        };
 
-        (App { regs }, Task::none())
+        let config = backend::Config::default();
+        (
+            App {
+                tree,
+                backend: Arc::new(simulator),
+                config,
+                timeout_input: config.timeout.as_millis().to_string(),
+                retries_input: config.retries.to_string(),
+                unconfirmed_read: None,
+                bulk_read: None,
+                device: Arc::new(device),
+                script_panel: ScriptPanel::new(&script),
+                show_script: false,
+                macro_panel: MacroPanel::new(steps),
+            },
+            Task::none(),
+        )
     }
 
     fn update(&mut self, message: Message) -> Task<Message> {
         match message {
-            Message::Reg(idx, msg) => match msg {
-                reg16::Message::Select => {
-                    for (j, reg) in self.regs.iter_mut().enumerate() {
-                        if idx != j {
-                            reg.state = ValState::None;
-                        }
-                        for field in reg.fields.iter_mut() {
-                            field.state = ValState::None;
-                        }
-                    }
-                    let _ = self.regs[idx].update(reg16::Message::Select);
-                    return text_input::focus(self.regs[idx].input_id.clone());
+            Message::TimeoutChanged(val) => {
+                if let Ok(ms) = val.trim().parse::<u64>() {
+                    self.config.timeout = Duration::from_millis(ms);
                 }
-                reg16::Message::FieldChanged(field_idx, field::Message::Select(id)) => {
-                    for (j, reg) in self.regs.iter_mut().enumerate() {
-                        reg.state = ValState::None;
-                        for (k, field) in reg.fields.iter_mut().enumerate() {
-                            if !(idx == j && field_idx == k) {
-                                field.state = ValState::None;
-                            }
+                self.timeout_input = val;
+            }
+            Message::RetriesChanged(val) => {
+                if let Ok(retries) = val.trim().parse::<u32>() {
+                    self.config.retries = retries;
+                }
+                self.retries_input = val;
+            }
+            Message::ReadAll => return self.read_all((0..self.tree.peripherals.len()).collect()),
+            Message::ConfirmRead(with_side_effects) => {
+                if let Some(periphs) = self.unconfirmed_read.take() {
+                    return self.start_bulk_read(periphs, with_side_effects);
+                }
+            }
+            Message::CancelRead => {
+                self.unconfirmed_read = None;
+                if let Some(bulk_read) = self.bulk_read.as_mut() {
+                    for batch in bulk_read.queue.drain(..) {
+                        for target in batch.targets {
+                            self.tree.peripherals[target.periph].regs[target.reg].io =
+                                IoState::Idle;
                         }
                     }
-                    let _ = self.regs[idx].update(reg16::Message::FieldChanged(
-                        field_idx,
-                        field::Message::Select(id.clone()),
-                    ));
-                    return text_input::focus(id.clone());
                 }
-                _ => match self.regs[idx].update(msg) {
-                    reg16::Action::None => (),
-                    reg16::Action::Read => println!("Read {}", self.regs[idx].name),
-                    reg16::Action::Write => println!(
-                        "Write 0x{:04X} to {}",
-                        self.regs[idx].value_write, self.regs[idx].name
-                    ),
-                    reg16::Action::Run(task) => return task.map(move |msg| Message::Reg(idx, msg)),
-                },
-            },
+            }
+            Message::BulkReadDone(result) => return self.bulk_read_done(result),
+            Message::DismissSummary => self.bulk_read = None,
+            Message::ExportC => return iced::clipboard::write(export::c_code(self.tree.marked())),
+            Message::ExportRust => {
+                return iced::clipboard::write(export::rust_code(self.tree.marked()))
+            }
+            Message::ExportDoc => self.export_doc(),
+            Message::ToggleScript => self.show_script = !self.show_script,
+            Message::Script(msg) => return self.script_action(msg),
+            Message::Macro(msg) => {
+                if let macro_panel::Message::StepDone(Ok(Some((target, value)))) = msg {
+                    let _ = self.tree.update(reg_tree::Message::Written(target, value));
+                }
+                return self.macro_action(msg);
+            }
+            Message::Tree(msg) => {
+                let action = self.tree.update(msg);
+                return self.tree_action(action);
+            }
+            Message::WriteDone(target, value, result) => {
+                if result.is_ok() {
+                    let periph = &self.tree.peripherals[target.periph];
+                    let path = format!("{}.{}", periph.name, periph.regs[target.reg].model.name);
+                    self.macro_panel.record(path, value);
+                }
+                let action = self
+                    .tree
+                    .update(reg_tree::Message::WriteDone(target, result));
+                return self.tree_action(action);
+            }
         }
         Task::none()
     }
 
-    fn view(&self) -> Column<Message> {
-        column(
-            self.regs
+    fn subscription(&self) -> Subscription<Message> {
+        self.tree.subscription().map(Message::Tree)
+    }
+
+    /// Writes the register reference documents with the values read so far.
+    fn export_doc(&self) {
+        let mut values = doc::Values::new();
+        for reg in self.tree.peripherals.iter().flat_map(|periph| &periph.regs) {
+            if matches!(reg.io, IoState::Ok) {
+                values.insert(reg.model.address, reg.value_read);
+            }
+        }
+        for (path, format) in [
+            (doc::MARKDOWN_FILE, DocFormat::Markdown),
+            (doc::HTML_FILE, DocFormat::Html),
+        ] {
+            if let Err(err) = std::fs::write(path, doc::render(&self.device, &values, format)) {
+                eprintln!("Failed to save {}: {}", path, err);
+            }
+        }
+    }
+
+    fn script_action(&mut self, message: script_panel::Message) -> Task<Message> {
+        match self.script_panel.update(message) {
+            script_panel::Action::None => Task::none(),
+            script_panel::Action::Run(src) => {
+                if let Err(err) = std::fs::write(script::SCRIPT_FILE, &src) {
+                    eprintln!("Failed to save {}: {}", script::SCRIPT_FILE, err);
+                }
+                let (script, events) =
+                    Script::start(src, self.device.clone(), self.backend.clone(), self.config);
+                self.script_panel.running = Some(script);
+                Task::run(events, |event| {
+                    Message::Script(script_panel::Message::Event(event))
+                })
+            }
+            script_panel::Action::Tree(msg) => {
+                let _ = self.tree.update(msg);
+                Task::none()
+            }
+        }
+    }
+
+    fn macro_action(&mut self, message: macro_panel::Message) -> Task<Message> {
+        match self.macro_panel.update(message) {
+            macro_panel::Action::None => Task::none(),
+            macro_panel::Action::Play(step) => Task::perform(
+                macros::play(
+                    step,
+                    self.device.clone(),
+                    self.backend.clone(),
+                    self.config,
+                    self.macro_panel.speed,
+                ),
+                |result| Message::Macro(macro_panel::Message::StepDone(result)),
+            ),
+            macro_panel::Action::Recorded => {
+                if let Err(err) =
+                    macros::save(macros::MACRO_FILE, &self.macro_panel.steps, &self.device)
+                {
+                    eprintln!("Failed to save {}: {}", macros::MACRO_FILE, err);
+                }
+                Task::none()
+            }
+        }
+    }
+
+    /// Carries out the reads and writes requested by the tree on the backend.
+    fn tree_action(&mut self, action: reg_tree::Action) -> Task<Message> {
+        match action {
+            reg_tree::Action::None => Task::none(),
+            reg_tree::Action::Read(target) => Task::perform(
+                backend::read(self.backend.clone(), self.config, target.address),
+                move |result| Message::Tree(reg_tree::Message::ReadDone(target, result)),
+            ),
+            reg_tree::Action::Write(target, value) => Task::perform(
+                backend::write(self.backend.clone(), self.config, target.address, value),
+                move |result| Message::WriteDone(target, value, result),
+            ),
+            reg_tree::Action::ReadAll(periphs) => self.read_all(periphs),
+            reg_tree::Action::FormatChanged => {
+                if let Err(err) = format::save(format::FORMATS_FILE, self.tree.formats()) {
+                    eprintln!("Failed to save {}: {}", format::FORMATS_FILE, err);
+                }
+                Task::none()
+            }
+            reg_tree::Action::Run(task) => task.map(Message::Tree),
+        }
+    }
+
+    /// Reads every register of the given peripherals, asking first if some of
+    /// them have read side effects.
+    fn read_all(&mut self, periphs: Vec<usize>) -> Task<Message> {
+        if self
+            .bulk_read
+            .as_ref()
+            .is_some_and(|bulk| !bulk.is_finished())
+        {
+            return Task::none();
+        }
+        let side_effects = periphs.iter().any(|&p| {
+            self.tree.peripherals[p]
+                .regs
+                .iter()
+                .any(|reg| reg.model.read_side_effects)
+        });
+        if side_effects {
+            self.unconfirmed_read = Some(periphs);
+            Task::none()
+        } else {
+            self.start_bulk_read(periphs, false)
+        }
+    }
+
+    fn start_bulk_read(&mut self, periphs: Vec<usize>, with_side_effects: bool) -> Task<Message> {
+        let mut targets = vec![];
+        let mut skipped = 0;
+        for p in periphs {
+            for (idx, reg) in self.tree.peripherals[p].regs.iter_mut().enumerate() {
+                if reg.model.read_side_effects && !with_side_effects {
+                    skipped += 1;
+                    continue;
+                }
+                reg.io = IoState::Pending;
+                targets.push(Target {
+                    periph: p,
+                    reg: idx,
+                    address: reg.model.address,
+                });
+            }
+        }
+        self.bulk_read = Some(BulkRead::new(
+            targets,
+            skipped,
+            self.backend.supports_block_read(),
+        ));
+        self.next_batch()
+    }
+
+    fn next_batch(&mut self) -> Task<Message> {
+        let Some(bulk_read) = self.bulk_read.as_mut() else {
+            return Task::none();
+        };
+        let Some(batch) = bulk_read.queue.pop_front() else {
+            return Task::none();
+        };
+        let task = Task::perform(
+            backend::read_block(
+                self.backend.clone(),
+                self.config,
+                batch.address,
+                batch.targets.len(),
+            ),
+            Message::BulkReadDone,
+        );
+        bulk_read.current = Some(batch);
+        task
+    }
+
+    fn bulk_read_done(&mut self, result: Result<Vec<u16>, backend::Error>) -> Task<Message> {
+        let Some(bulk_read) = self.bulk_read.as_mut() else {
+            return Task::none();
+        };
+        let Some(batch) = bulk_read.current.take() else {
+            return Task::none();
+        };
+        for (i, target) in batch.targets.iter().enumerate() {
+            let result = match &result {
+                Ok(values) => Ok(values[i]),
+                Err(err) => {
+                    let periph = &self.tree.peripherals[target.periph];
+                    let reg = &periph.regs[target.reg];
+                    bulk_read
+                        .failures
+                        .push((format!("{}.{}", periph.name, reg.model.name), err.clone()));
+                    Err(err.clone())
+                }
+            };
+            let _ = self
+                .tree
+                .update(reg_tree::Message::ReadDone(*target, result));
+        }
+        bulk_read.done += batch.targets.len();
+        self.next_batch()
+    }
+
+    fn view(&self) -> Column<'_, Message> {
+        let marked = self.tree.marked().next().is_some();
+        let settings = row![
+            text("timeout [ms]"),
+            text_input("", self.timeout_input.as_str())
+                .width(80)
+                .on_input(Message::TimeoutChanged),
+            text("read retries"),
+            text_input("", self.retries_input.as_str())
+                .width(80)
+                .on_input(Message::RetriesChanged),
+            button(text("Read all")).padding([0, 5]).on_press_maybe(
+                (self.unconfirmed_read.is_none()
+                    && self.bulk_read.as_ref().is_none_or(BulkRead::is_finished))
+                .then_some(Message::ReadAll)
+            ),
+            button(text("Export C"))
+                .padding([0, 5])
+                .on_press_maybe(marked.then_some(Message::ExportC)),
+            button(text("Export Rust"))
+                .padding([0, 5])
+                .on_press_maybe(marked.then_some(Message::ExportRust)),
+            button(text("Export doc"))
+                .padding([0, 5])
+                .on_press(Message::ExportDoc),
+            button(text("Script"))
+                .padding([0, 5])
+                .on_press(Message::ToggleScript),
+        ]
+        .spacing(10);
+        column![settings, self.macro_panel.view().map(Message::Macro)]
+            .push_maybe(
+                self.show_script
+                    .then(|| self.script_panel.view().map(Message::Script)),
+            )
+            .push_maybe(self.unconfirmed_read.as_ref().map(|_| self.confirm_view()))
+            .push_maybe(self.bulk_read.as_ref().map(Self::bulk_read_view))
+            .push(self.tree.view().map(Message::Tree))
+            .padding(20)
+            .spacing(20)
+    }
+
+    fn confirm_view(&self) -> Element<'_, Message> {
+        row![
+            text("Some registers have read side effects."),
+            button(text("Skip them"))
+                .padding([0, 5])
+                .on_press(Message::ConfirmRead(false)),
+            button(text("Read them too"))
+                .padding([0, 5])
+                .on_press(Message::ConfirmRead(true)),
+            button(text("Cancel"))
+                .padding([0, 5])
+                .on_press(Message::CancelRead),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn bulk_read_view(bulk_read: &BulkRead) -> Element<'_, Message> {
+        if !bulk_read.is_finished() {
+            return row![
+                text(format!("Reading {}/{}", bulk_read.done, bulk_read.total)),
+                progress_bar(0.0..=bulk_read.total as f32, bulk_read.done as f32)
+                    .width(200)
+                    .height(15),
+                button(text("Cancel"))
+                    .padding([0, 5])
+                    .on_press(Message::CancelRead),
+            ]
+            .spacing(10)
+            .into();
+        }
+        let mut summary = format!(
+            "Read {} registers, {} failed",
+            bulk_read.done,
+            bulk_read.failures.len()
+        );
+        if bulk_read.skipped > 0 {
+            summary += format!(", {} skipped", bulk_read.skipped).as_str();
+        }
+        column![row![
+            text(summary),
+            button(text("Dismiss"))
+                .padding([0, 5])
+                .on_press(Message::DismissSummary),
+        ]
+        .spacing(10)]
+        .extend(
+            bulk_read
+                .failures
                 .iter()
-                .map(Reg16::view)
-                .enumerate()
-                .map(|(index, reg)| reg.map(move |message| Message::Reg(index, message))),
+                .map(|(name, err)| text(format!("{}: {}", name, err)).into()),
         )
-        .padding(20)
-        .spacing(20)
+        .spacing(5)
+        .into()
     }
 }
@@ -0,0 +1,594 @@
+//! Dropdown menu of a [`ComboBox`](crate::combo_box::ComboBox) highlighting
+//! the characters matched by the search query.
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::renderer;
+use iced::advanced::text::{self, Paragraph, Span, Text};
+use iced::advanced::widget::Tree;
+use iced::advanced::{Clipboard, Shell, Widget};
+use iced::alignment;
+use iced::border;
+use iced::event::{self, Event};
+use iced::mouse;
+use iced::overlay;
+use iced::overlay::menu::Catalog;
+use iced::touch;
+use iced::widget::scrollable::{self, Scrollable};
+use iced::{Background, Color, Element, Length, Padding, Pixels, Point, Rectangle, Size, Vector};
+
+/// A list of selectable options.
+#[allow(missing_debug_implementations)]
+pub struct Menu<'a, 'b, T, Message, Theme, Renderer>
+where
+    Theme: Catalog,
+    Renderer: text::Renderer,
+    'b: 'a,
+{
+    state: &'a mut State,
+    options: &'a [T],
+    highlights: &'a [Vec<usize>],
+    hovered_option: &'a mut Option<usize>,
+    on_selected: Box<dyn FnMut(T) -> Message + 'a>,
+    on_option_hovered: Option<&'a dyn Fn(T) -> Message>,
+    width: f32,
+    padding: Padding,
+    text_size: Option<Pixels>,
+    text_line_height: text::LineHeight,
+    font: Option<Renderer::Font>,
+    class: &'a <Theme as Catalog>::Class<'b>,
+}
+
+impl<'a, 'b, T, Message, Theme, Renderer> Menu<'a, 'b, T, Message, Theme, Renderer>
+where
+    T: ToString + Describe + Clone,
+    Message: 'a,
+    Theme: Catalog + 'a,
+    Renderer: text::Renderer + 'a,
+    'b: 'a,
+{
+    /// Creates a new [`Menu`] with the given [`State`], a list of options,
+    /// the indices of the matched characters of every option, and the message
+    /// to produced when an option is selected.
+    pub fn new(
+        state: &'a mut State,
+        options: &'a [T],
+        highlights: &'a [Vec<usize>],
+        hovered_option: &'a mut Option<usize>,
+        on_selected: impl FnMut(T) -> Message + 'a,
+        on_option_hovered: Option<&'a dyn Fn(T) -> Message>,
+        class: &'a <Theme as Catalog>::Class<'b>,
+    ) -> Self {
+        Menu {
+            state,
+            options,
+            highlights,
+            hovered_option,
+            on_selected: Box::new(on_selected),
+            on_option_hovered,
+            width: 0.0,
+            padding: Padding::ZERO,
+            text_size: None,
+            text_line_height: text::LineHeight::default(),
+            font: None,
+            class,
+        }
+    }
+
+    /// Sets the width of the [`Menu`].
+    pub fn width(mut self, width: f32) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Sets the [`Padding`] of the [`Menu`].
+    pub fn padding<P: Into<Padding>>(mut self, padding: P) -> Self {
+        self.padding = padding.into();
+        self
+    }
+
+    /// Sets the text size of the [`Menu`].
+    pub fn text_size(mut self, text_size: impl Into<Pixels>) -> Self {
+        self.text_size = Some(text_size.into());
+        self
+    }
+
+    /// Sets the font of the [`Menu`].
+    pub fn font(mut self, font: impl Into<Renderer::Font>) -> Self {
+        self.font = Some(font.into());
+        self
+    }
+
+    /// Turns the [`Menu`] into an overlay [`Element`] at the given target
+    /// position.
+    ///
+    /// The `target_height` will be used to display the menu either on top
+    /// of the target or under it, depending on the screen position and the
+    /// dimensions of the [`Menu`].
+    pub fn overlay(
+        self,
+        position: Point,
+        target_height: f32,
+    ) -> overlay::Element<'a, Message, Theme, Renderer> {
+        overlay::Element::new(Box::new(Overlay::new(position, self, target_height)))
+    }
+}
+
+/// An option that can show a description next to its name in a [`Menu`].
+pub trait Describe {
+    /// Secondary text of the option, displayed dimmed after it.
+    fn description(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// The local state of a [`Menu`].
+#[derive(Debug)]
+pub struct State {
+    tree: Tree,
+}
+
+impl State {
+    /// Creates a new [`State`] for a [`Menu`].
+    pub fn new() -> Self {
+        Self {
+            tree: Tree::empty(),
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Overlay<'a, 'b, Message, Theme, Renderer>
+where
+    Theme: Catalog,
+    Renderer: iced::advanced::Renderer,
+{
+    position: Point,
+    state: &'a mut Tree,
+    list: Scrollable<'a, Message, Theme, Renderer>,
+    width: f32,
+    target_height: f32,
+    class: &'a <Theme as Catalog>::Class<'b>,
+}
+
+impl<'a, 'b, Message, Theme, Renderer> Overlay<'a, 'b, Message, Theme, Renderer>
+where
+    Message: 'a,
+    Theme: Catalog + scrollable::Catalog + 'a,
+    Renderer: text::Renderer + 'a,
+    'b: 'a,
+{
+    pub fn new<T>(
+        position: Point,
+        menu: Menu<'a, 'b, T, Message, Theme, Renderer>,
+        target_height: f32,
+    ) -> Self
+    where
+        T: Clone + ToString + Describe,
+    {
+        let Menu {
+            state,
+            options,
+            highlights,
+            hovered_option,
+            on_selected,
+            on_option_hovered,
+            width,
+            padding,
+            font,
+            text_size,
+            text_line_height,
+            class,
+        } = menu;
+
+        let list = Scrollable::new(List {
+            options,
+            highlights,
+            hovered_option,
+            on_selected,
+            on_option_hovered,
+            font,
+            text_size,
+            text_line_height,
+            padding,
+            class,
+        });
+
+        state.tree.diff(&list as &dyn Widget<_, _, _>);
+
+        Self {
+            position,
+            state: &mut state.tree,
+            list,
+            width,
+            target_height,
+            class,
+        }
+    }
+}
+
+impl<'a, 'b, Message, Theme, Renderer> iced::advanced::Overlay<Message, Theme, Renderer>
+    for Overlay<'a, 'b, Message, Theme, Renderer>
+where
+    Theme: Catalog,
+    Renderer: text::Renderer,
+{
+    fn layout(&mut self, renderer: &Renderer, bounds: Size) -> layout::Node {
+        let space_below = bounds.height - (self.position.y + self.target_height);
+        let space_above = self.position.y;
+
+        let limits = layout::Limits::new(
+            Size::ZERO,
+            Size::new(
+                bounds.width - self.position.x,
+                if space_below > space_above {
+                    space_below
+                } else {
+                    space_above
+                },
+            ),
+        )
+        .width(self.width);
+
+        let node = self.list.layout(self.state, renderer, &limits);
+        let size = node.size();
+
+        node.move_to(if space_below > space_above {
+            self.position + Vector::new(0.0, self.target_height)
+        } else {
+            self.position - Vector::new(0.0, size.height)
+        })
+    }
+
+    fn on_event(
+        &mut self,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) -> event::Status {
+        let bounds = layout.bounds();
+
+        self.list.on_event(
+            self.state, event, layout, cursor, renderer, clipboard, shell, &bounds,
+        )
+    }
+
+    fn mouse_interaction(
+        &self,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.list
+            .mouse_interaction(self.state, layout, cursor, viewport, renderer)
+    }
+
+    fn draw(
+        &self,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        defaults: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+    ) {
+        let bounds = layout.bounds();
+
+        let style = Catalog::style(theme, self.class);
+
+        renderer.fill_quad(
+            renderer::Quad {
+                bounds,
+                border: style.border,
+                ..renderer::Quad::default()
+            },
+            style.background,
+        );
+
+        self.list.draw(
+            self.state, renderer, theme, defaults, layout, cursor, &bounds,
+        );
+    }
+}
+
+struct List<'a, 'b, T, Message, Theme, Renderer>
+where
+    Theme: Catalog,
+    Renderer: text::Renderer,
+{
+    options: &'a [T],
+    highlights: &'a [Vec<usize>],
+    hovered_option: &'a mut Option<usize>,
+    on_selected: Box<dyn FnMut(T) -> Message + 'a>,
+    on_option_hovered: Option<&'a dyn Fn(T) -> Message>,
+    padding: Padding,
+    text_size: Option<Pixels>,
+    text_line_height: text::LineHeight,
+    font: Option<Renderer::Font>,
+    class: &'a <Theme as Catalog>::Class<'b>,
+}
+
+impl<T, Message, Theme, Renderer> List<'_, '_, T, Message, Theme, Renderer>
+where
+    Theme: Catalog,
+    Renderer: text::Renderer,
+{
+    fn option_height(&self, renderer: &Renderer) -> f32 {
+        let text_size = self.text_size.unwrap_or_else(|| renderer.default_size());
+
+        f32::from(self.text_line_height.to_absolute(text_size)) + self.padding.vertical()
+    }
+}
+
+impl<T, Message, Theme, Renderer> Widget<Message, Theme, Renderer>
+    for List<'_, '_, T, Message, Theme, Renderer>
+where
+    T: Clone + ToString + Describe,
+    Theme: Catalog,
+    Renderer: text::Renderer,
+{
+    fn size(&self) -> Size<Length> {
+        Size {
+            width: Length::Fill,
+            height: Length::Shrink,
+        }
+    }
+
+    fn layout(
+        &self,
+        _tree: &mut Tree,
+        renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        let size = {
+            let intrinsic = Size::new(
+                0.0,
+                self.option_height(renderer) * self.options.len() as f32,
+            );
+
+            limits.resolve(Length::Fill, Length::Shrink, intrinsic)
+        };
+
+        layout::Node::new(size)
+    }
+
+    fn on_event(
+        &mut self,
+        _state: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) -> event::Status {
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left))
+                if cursor.is_over(layout.bounds()) =>
+            {
+                if let Some(index) = *self.hovered_option {
+                    if let Some(option) = self.options.get(index) {
+                        shell.publish((self.on_selected)(option.clone()));
+                        return event::Status::Captured;
+                    }
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                if let Some(cursor_position) = cursor.position_in(layout.bounds()) {
+                    let new_hovered_option =
+                        (cursor_position.y / self.option_height(renderer)) as usize;
+
+                    if let Some(on_option_hovered) = self.on_option_hovered {
+                        if *self.hovered_option != Some(new_hovered_option) {
+                            if let Some(option) = self.options.get(new_hovered_option) {
+                                shell.publish(on_option_hovered(option.clone()));
+                            }
+                        }
+                    }
+
+                    *self.hovered_option = Some(new_hovered_option);
+                }
+            }
+            Event::Touch(touch::Event::FingerPressed { .. }) => {
+                if let Some(cursor_position) = cursor.position_in(layout.bounds()) {
+                    *self.hovered_option =
+                        Some((cursor_position.y / self.option_height(renderer)) as usize);
+
+                    if let Some(index) = *self.hovered_option {
+                        if let Some(option) = self.options.get(index) {
+                            shell.publish((self.on_selected)(option.clone()));
+                            return event::Status::Captured;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        event::Status::Ignored
+    }
+
+    fn mouse_interaction(
+        &self,
+        _state: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        let is_mouse_over = cursor.is_over(layout.bounds());
+
+        if is_mouse_over {
+            mouse::Interaction::Pointer
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+
+    fn draw(
+        &self,
+        _state: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        let style = Catalog::style(theme, self.class);
+        let bounds = layout.bounds();
+
+        let text_size = self.text_size.unwrap_or_else(|| renderer.default_size());
+        let option_height = self.option_height(renderer);
+
+        let offset = viewport.y - bounds.y;
+        let start = (offset / option_height) as usize;
+        let end = ((offset + viewport.height) / option_height).ceil() as usize;
+
+        let visible_options = &self.options[start..end.min(self.options.len())];
+
+        for (i, option) in visible_options.iter().enumerate() {
+            let i = start + i;
+            let is_selected = *self.hovered_option == Some(i);
+
+            let bounds = Rectangle {
+                x: bounds.x,
+                y: bounds.y + (option_height * i as f32),
+                width: bounds.width,
+                height: option_height,
+            };
+
+            if is_selected {
+                renderer.fill_quad(
+                    renderer::Quad {
+                        bounds: Rectangle {
+                            x: bounds.x + style.border.width,
+                            width: bounds.width - style.border.width * 2.0,
+                            ..bounds
+                        },
+                        border: border::rounded(style.border.radius),
+                        ..renderer::Quad::default()
+                    },
+                    style.selected_background,
+                );
+            }
+
+            let (text_color, highlight_color) = if is_selected {
+                (style.selected_text_color, style.selected_text_color)
+            } else {
+                let highlight_color = match style.selected_background {
+                    Background::Color(color) => color,
+                    Background::Gradient(_) => style.text_color,
+                };
+                (style.text_color, highlight_color)
+            };
+
+            let content = option.to_string();
+            let highlights = self.highlights.get(i).map_or(&[][..], Vec::as_slice);
+            let mut spans = highlighted_spans(&content, highlights, highlight_color);
+            let matched_spans = spans.len();
+            if let Some(description) = option.description() {
+                spans.push(Span::new("   "));
+                spans.push(Span::new(description).color(Color {
+                    a: 0.5,
+                    ..text_color
+                }));
+            }
+
+            let paragraph = Renderer::Paragraph::with_spans(Text {
+                content: spans.as_slice(),
+                bounds: Size::new(f32::INFINITY, bounds.height),
+                size: text_size,
+                line_height: self.text_line_height,
+                font: self.font.unwrap_or_else(|| renderer.default_font()),
+                horizontal_alignment: alignment::Horizontal::Left,
+                vertical_alignment: alignment::Vertical::Center,
+                shaping: text::Shaping::Basic,
+                wrapping: text::Wrapping::None,
+            });
+            let position = Point::new(bounds.x + self.padding.left, bounds.center_y());
+
+            // Underline the matched characters so they stand out on the
+            // hovered option as well
+            for (index, span) in spans[..matched_spans].iter().enumerate() {
+                if span.color.is_none() {
+                    continue;
+                }
+                for span_bounds in paragraph.span_bounds(index) {
+                    renderer.fill_quad(
+                        renderer::Quad {
+                            bounds: Rectangle {
+                                x: position.x + span_bounds.x,
+                                y: bounds.y + bounds.height - self.padding.bottom - 2.0,
+                                width: span_bounds.width,
+                                height: 1.0,
+                            },
+                            ..renderer::Quad::default()
+                        },
+                        highlight_color,
+                    );
+                }
+            }
+
+            // Long descriptions are cut off at the edge of the menu
+            let clip_bounds = bounds.intersection(viewport).unwrap_or(bounds);
+            renderer.fill_paragraph(&paragraph, position, text_color, clip_bounds);
+        }
+    }
+}
+
+/// Splits `content` into spans, coloring the characters at the `highlights`
+/// indices.
+fn highlighted_spans<'a, Font>(
+    content: &'a str,
+    highlights: &[usize],
+    color: Color,
+) -> Vec<Span<'a, (), Font>> {
+    let mut spans = vec![];
+    let mut start = 0;
+    let mut highlighted = false;
+    for (index, (byte, _)) in content.char_indices().enumerate() {
+        let is_highlighted = highlights.contains(&index);
+        if is_highlighted != highlighted {
+            if byte > start {
+                spans.push(span(&content[start..byte], highlighted, color));
+            }
+            start = byte;
+            highlighted = is_highlighted;
+        }
+    }
+    if start < content.len() {
+        spans.push(span(&content[start..], highlighted, color));
+    }
+    spans
+}
+
+fn span<Font>(text: &str, highlighted: bool, color: Color) -> Span<'_, (), Font> {
+    let span = Span::new(text);
+    if highlighted {
+        span.color(color)
+    } else {
+        span
+    }
+}
+
+impl<'a, 'b, T, Message, Theme, Renderer> From<List<'a, 'b, T, Message, Theme, Renderer>>
+    for Element<'a, Message, Theme, Renderer>
+where
+    T: ToString + Describe + Clone,
+    Message: 'a,
+    Theme: 'a + Catalog,
+    Renderer: 'a + text::Renderer,
+    'b: 'a,
+{
+    fn from(list: List<'a, 'b, T, Message, Theme, Renderer>) -> Self {
+        Element::new(list)
+    }
+}
@@ -0,0 +1,423 @@
+//! Description of the device registers, independent of any user interface.
+//!
+//! The model holds what the SVD file says about peripherals, registers, fields
+//! and their enumerated values, and knows how to decode field values from a
+//! register value and encode them back. It keeps no register values itself.
+
+use svd_parser as svd;
+
+/// Error of [`Device::parse_svd`].
+pub type ParseError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Width of the registers in bits.
+pub const REG_BITS: u8 = 16;
+
+/// Parses decimal, `0x` hexadecimal, `0b` binary and Verilog style (`16'h1F`)
+/// numbers.
+pub fn parse_number(src: &str) -> Option<u64> {
+    let src = src.replace('_', "");
+    if let Some((_, value)) = src.split_once('\'') {
+        let (radix, digits) = match value.chars().next()?.to_ascii_lowercase() {
+            'h' => (16, &value[1..]),
+            'd' => (10, &value[1..]),
+            'b' => (2, &value[1..]),
+            'o' => (8, &value[1..]),
+            _ => return None,
+        };
+        return u64::from_str_radix(digits, radix).ok();
+    }
+    if let Some(hex) = src.strip_prefix("0x").or_else(|| src.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = src.strip_prefix("0b").or_else(|| src.strip_prefix("0B")) {
+        u64::from_str_radix(bin, 2).ok()
+    } else {
+        src.parse().ok()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub name: String,
+    pub peripherals: Vec<Peripheral>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Peripheral {
+    pub name: String,
+    pub description: Option<String>,
+    pub base_address: u32,
+    /// Registers in description order, those of clusters included.
+    pub registers: Vec<Register>,
+    pub clusters: Vec<Cluster>,
+}
+
+/// A group of registers within a peripheral, an instance of an SVD
+/// `<cluster>`. The names of its registers and nested clusters are prefixed
+/// with its name and `_`, keeping the `PERIPH.REG` paths unique.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    /// Name prefixed with those of the enclosing clusters, like `CH0_SUB`.
+    pub name: String,
+    pub description: Option<String>,
+    pub address: u32,
+    /// Index of the enclosing cluster in the peripheral.
+    pub parent: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Register {
+    pub name: String,
+    pub description: Option<String>,
+    pub address: u32,
+    pub reset_value: u16,
+    /// Reading the register changes the device state, e.g. clears flags.
+    pub read_side_effects: bool,
+    pub fields: Vec<Field>,
+    /// Index of the enclosing cluster in the peripheral.
+    pub cluster: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub description: Option<String>,
+    pub offset: u8,
+    pub width: u8,
+    pub access: Access,
+    pub write_constraint: Option<WriteConstraint>,
+    pub enum_values: Vec<EnumValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumValue {
+    pub name: String,
+    pub description: Option<String>,
+    pub value: u16,
+}
+
+/// Software access to a field.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Access {
+    #[default]
+    ReadWrite,
+    ReadOnly,
+    WriteOnly,
+}
+
+/// Restriction of the values that may be written to a field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteConstraint {
+    Range { min: u16, max: u16 },
+    EnumeratedValues,
+    WriteAsRead,
+}
+
+/// Why a value can't be written to a field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueError {
+    TooWide,
+    OutOfRange { min: u16, max: u16 },
+    NotEnumerated,
+    NotRead,
+    ReadOnly,
+}
+
+impl std::fmt::Display for ValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueError::TooWide => f.write_str("value too wide for the field"),
+            ValueError::OutOfRange { min, max } => write!(f, "allowed {}..={}", min, max),
+            ValueError::NotEnumerated => f.write_str("only enumerated values allowed"),
+            ValueError::NotRead => f.write_str("only the read value can be written"),
+            ValueError::ReadOnly => f.write_str("read-only field"),
+        }
+    }
+}
+
+impl Device {
+    /// Parses an SVD file, expanding derived and array elements.
+    pub fn parse_svd(xml: &str) -> Result<Device, ParseError> {
+        let device = svd::parse_with_config(
+            xml,
+            &svd::Config::default().expand(true).expand_properties(true),
+        )
+        .map_err(ParseError::from)?;
+        Ok(Device::from_svd(&device))
+    }
+
+    /// Builds the model of an expanded SVD device.
+    pub fn from_svd(device: &svd::svd::Device) -> Device {
+        Device {
+            name: device.name.clone(),
+            peripherals: device
+                .peripherals
+                .iter()
+                .map(|periph| {
+                    let mut model = Peripheral {
+                        name: periph.name.clone(),
+                        description: periph.description.clone(),
+                        base_address: periph.base_address as u32,
+                        registers: vec![],
+                        clusters: vec![],
+                    };
+                    let children = periph.registers.as_deref().unwrap_or_default();
+                    model.add_svd_children(children, periph.base_address, None);
+                    model
+                })
+                .collect(),
+        }
+    }
+
+    pub fn peripheral(&self, name: &str) -> Option<&Peripheral> {
+        self.peripherals.iter().find(|periph| periph.name == name)
+    }
+
+    /// Indices of the peripheral and the register at the `PERIPH.REG` path.
+    pub fn register_position(&self, path: &str) -> Option<(usize, usize)> {
+        let (periph, reg) = path.split_once('.')?;
+        let p = self
+            .peripherals
+            .iter()
+            .position(|candidate| candidate.name == periph)?;
+        let r = self.peripherals[p]
+            .registers
+            .iter()
+            .position(|candidate| candidate.name == reg)?;
+        Some((p, r))
+    }
+
+    /// Looks up a register by its `PERIPH.REG` path.
+    pub fn register(&self, path: &str) -> Option<(&Peripheral, &Register)> {
+        let (periph, reg) = path.split_once('.')?;
+        let periph = self.peripheral(periph)?;
+        Some((periph, periph.register(reg)?))
+    }
+}
+
+impl Peripheral {
+    pub fn register(&self, name: &str) -> Option<&Register> {
+        self.registers.iter().find(|reg| reg.name == name)
+    }
+
+    /// Adds the registers and clusters of an SVD peripheral or cluster at
+    /// `address`, recursing into the clusters.
+    fn add_svd_children(
+        &mut self,
+        children: &[svd::svd::RegisterCluster],
+        address: u64,
+        cluster: Option<usize>,
+    ) {
+        let prefix = match cluster {
+            Some(c) => format!("{}_", self.clusters[c].name),
+            None => String::new(),
+        };
+        for child in children {
+            match child {
+                svd::svd::RegisterCluster::Register(reg) => {
+                    let mut reg = Register::from_svd(address, reg);
+                    reg.name = format!("{}{}", prefix, reg.name);
+                    reg.cluster = cluster;
+                    self.registers.push(reg);
+                }
+                svd::svd::RegisterCluster::Cluster(svd_cluster) => {
+                    let address = address + svd_cluster.address_offset as u64;
+                    self.clusters.push(Cluster {
+                        name: format!("{}{}", prefix, svd_cluster.name),
+                        description: svd_cluster.description.clone(),
+                        address: address as u32,
+                        parent: cluster,
+                    });
+                    let index = self.clusters.len() - 1;
+                    self.add_svd_children(&svd_cluster.children, address, Some(index));
+                }
+            }
+        }
+    }
+
+    /// Name of the cluster or register without the prefix of its enclosing
+    /// cluster.
+    pub fn short_name<'a>(&self, name: &'a str, cluster: Option<usize>) -> &'a str {
+        cluster
+            .and_then(|c| name.strip_prefix(self.clusters[c].name.as_str()))
+            .and_then(|name| name.strip_prefix('_'))
+            .unwrap_or(name)
+    }
+}
+
+impl Register {
+    fn from_svd(base_address: u64, reg: &svd::svd::Register) -> Register {
+        Register {
+            name: reg.name.clone(),
+            description: reg.description.clone(),
+            address: (base_address + reg.address_offset as u64) as u32,
+            reset_value: reg.properties.reset_value.unwrap_or(0) as u16,
+            read_side_effects: reg.read_action.is_some()
+                || reg.fields().any(|field| field.read_action.is_some()),
+            fields: reg
+                .fields()
+                .map(|field| Field::from_svd(field, reg.properties.access))
+                .collect(),
+            cluster: None,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Values of all fields in the register value `reg`.
+    pub fn decode(&self, reg: u16) -> impl Iterator<Item = (&Field, u16)> {
+        self.fields
+            .iter()
+            .map(move |field| (field, field.decode(reg)))
+    }
+}
+
+impl Field {
+    /// Builds the model of a field, with the `access` of its register if it has
+    /// none of its own.
+    fn from_svd(field: &svd::svd::Field, access: Option<svd::svd::Access>) -> Field {
+        let mut enum_values = vec![];
+        for svd_enum_values in field.enumerated_values.iter() {
+            for svd_enum_value in svd_enum_values.values.iter() {
+                if let Some(val) = svd_enum_value.value {
+                    enum_values.push(EnumValue {
+                        name: svd_enum_value.name.clone(),
+                        description: svd_enum_value.description.clone(),
+                        value: val as u16,
+                    })
+                }
+            }
+        }
+        Field {
+            name: field.name.clone(),
+            description: field.description.clone(),
+            offset: field.bit_range.offset as u8,
+            width: field.bit_range.width as u8,
+            access: match field.access.or(access) {
+                Some(svd::svd::Access::ReadOnly) => Access::ReadOnly,
+                Some(svd::svd::Access::WriteOnly | svd::svd::Access::WriteOnce) => {
+                    Access::WriteOnly
+                }
+                _ => Access::ReadWrite,
+            },
+            write_constraint: field
+                .write_constraint
+                .and_then(|constraint| match constraint {
+                    svd::svd::WriteConstraint::Range(range) => Some(WriteConstraint::Range {
+                        min: range.min as u16,
+                        max: range.max as u16,
+                    }),
+                    svd::svd::WriteConstraint::UseEnumeratedValues(true) => {
+                        Some(WriteConstraint::EnumeratedValues)
+                    }
+                    svd::svd::WriteConstraint::WriteAsRead(true) => {
+                        Some(WriteConstraint::WriteAsRead)
+                    }
+                    _ => None,
+                }),
+            enum_values,
+        }
+    }
+
+    pub fn max_value(&self) -> u16 {
+        !(0xffffu16.checked_shl(self.width as u32).unwrap_or(0))
+    }
+
+    /// Bits of the field within the register.
+    pub fn mask(&self) -> u16 {
+        self.max_value() << self.offset
+    }
+
+    /// Value of the field in the register value `reg`.
+    pub fn decode(&self, reg: u16) -> u16 {
+        (reg >> self.offset) & self.max_value()
+    }
+
+    /// Register value `reg` with the field set to `value`, truncated to the field width.
+    pub fn encode(&self, reg: u16, value: u16) -> u16 {
+        ((value << self.offset) & self.mask()) | (reg & !self.mask())
+    }
+
+    /// Checks `value` against the field width and its write constraint, `read`
+    /// being the value last read from the field.
+    pub fn validate(&self, value: u16, read: u16) -> Result<(), ValueError> {
+        if value > self.max_value() {
+            return Err(ValueError::TooWide);
+        }
+        if self.access == Access::ReadOnly && value != read {
+            return Err(ValueError::ReadOnly);
+        }
+        match self.write_constraint {
+            Some(WriteConstraint::Range { min, max }) if value < min || value > max => {
+                Err(ValueError::OutOfRange { min, max })
+            }
+            Some(WriteConstraint::EnumeratedValues) if !self.is_enumerated(value) => {
+                Err(ValueError::NotEnumerated)
+            }
+            Some(WriteConstraint::WriteAsRead) if value != read => Err(ValueError::NotRead),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `value` is an enumerated value, or any combination of the flags
+    /// of a flags field.
+    fn is_enumerated(&self, value: u16) -> bool {
+        if self.is_flags() {
+            let flags = self
+                .enum_values
+                .iter()
+                .fold(0, |bits, val| bits | val.value);
+            value & !flags == 0
+        } else {
+            self.enum_value(value).is_some()
+        }
+    }
+
+    /// The enumerated value equal to `value`, the last one if there are more.
+    pub fn enum_value(&self, value: u16) -> Option<&EnumValue> {
+        self.enum_values.iter().rfind(|val| val.value == value)
+    }
+
+    pub fn enum_value_by_name(&self, name: &str) -> Option<&EnumValue> {
+        self.enum_values.iter().find(|val| val.name == name)
+    }
+
+    /// Whether the enumerated values are independent single-bit flags, edited
+    /// as a set rather than picked one at a time.
+    ///
+    /// A two bit field enumerating 0, 1 and 2 is far more likely to be a mode
+    /// selection, so two flags only count when there's no zero value.
+    pub fn is_flags(&self) -> bool {
+        let flags = self.enum_values.iter().filter(|val| val.value != 0).count();
+        let has_zero = self.enum_values.iter().any(|val| val.value == 0);
+        (flags >= 3 || (flags == 2 && !has_zero))
+            && self
+                .enum_values
+                .iter()
+                .all(|val| val.value == 0 || val.value.is_power_of_two())
+    }
+
+    /// Flags set in `value`, for fields where [`is_flags`](Field::is_flags) holds.
+    pub fn flags(&self, value: u16) -> impl Iterator<Item = &EnumValue> {
+        self.enum_values
+            .iter()
+            .filter(move |val| val.value != 0 && value & val.value == val.value)
+    }
+
+    /// Name of `value`: its enumerated value, the flags set in it or `None`.
+    pub fn value_name(&self, value: u16) -> Option<String> {
+        if self.is_flags() {
+            let names: Vec<&str> = self.flags(value).map(|val| val.name.as_str()).collect();
+            (!names.is_empty()).then(|| names.join(" | "))
+        } else {
+            self.enum_value(value).map(|val| val.name.clone())
+        }
+    }
+}
+
+impl std::fmt::Display for EnumValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.name, self.value)
+    }
+}
@@ -0,0 +1,149 @@
+//! Keyboard navigation of the register tree.
+
+use iced::event::{self, Event};
+use iced::keyboard::{self, key, Key};
+use iced::{window, Subscription};
+
+use crate::peripheral::{self, Child, Peripheral, Sort};
+
+/// Position of the keyboard cursor in the tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cursor {
+    Peripheral(usize),
+    Cluster(usize, usize),
+    Reg(usize, usize),
+    Field(usize, usize, usize),
+}
+
+impl Cursor {
+    pub fn parent(self, peripherals: &[Peripheral]) -> Option<Cursor> {
+        let node = |p: usize, cluster: Option<usize>| match cluster {
+            Some(c) => Cursor::Cluster(p, c),
+            None => Cursor::Peripheral(p),
+        };
+        match self {
+            Cursor::Peripheral(_) => None,
+            Cursor::Cluster(p, c) => Some(node(p, peripherals[p].clusters[c].model.parent)),
+            Cursor::Reg(p, r) => Some(node(p, peripherals[p].regs[r].model.cluster)),
+            Cursor::Field(p, r, _) => Some(Cursor::Reg(p, r)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Up,
+    Down,
+    Collapse,
+    Expand,
+    Edit,
+    Cancel,
+    Read,
+    Write,
+    NextField,
+    PreviousField,
+}
+
+pub fn subscription() -> Subscription<Command> {
+    event::listen_with(command)
+}
+
+fn command(event: Event, status: event::Status, _window: window::Id) -> Option<Command> {
+    let Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) = event else {
+        return None;
+    };
+    // Text inputs capture Escape to lose focus, it cancels the edit as well
+    if key == Key::Named(key::Named::Escape) {
+        return Some(Command::Cancel);
+    }
+    if status == event::Status::Captured || modifiers.command() || modifiers.alt() {
+        return None;
+    }
+    match key.as_ref() {
+        Key::Named(key::Named::ArrowUp) => Some(Command::Up),
+        Key::Named(key::Named::ArrowDown) => Some(Command::Down),
+        Key::Named(key::Named::ArrowLeft) => Some(Command::Collapse),
+        Key::Named(key::Named::ArrowRight) => Some(Command::Expand),
+        Key::Named(key::Named::Enter) => Some(Command::Edit),
+        Key::Named(key::Named::Tab) if modifiers.shift() => Some(Command::PreviousField),
+        Key::Named(key::Named::Tab) => Some(Command::NextField),
+        Key::Character("r" | "R") => Some(Command::Read),
+        Key::Character("w" | "W") => Some(Command::Write),
+        _ => None,
+    }
+}
+
+/// Every position currently visible, in display order.
+pub fn visible(peripherals: &[Peripheral], sort: Sort) -> Vec<Cursor> {
+    let mut rows = vec![];
+    for p in peripheral::order(peripherals, sort) {
+        rows.push(Cursor::Peripheral(p));
+        if peripherals[p].expanded {
+            visible_children(&peripherals[p], p, None, sort, &mut rows);
+        }
+    }
+    rows
+}
+
+fn visible_children(
+    periph: &Peripheral,
+    p: usize,
+    cluster: Option<usize>,
+    sort: Sort,
+    rows: &mut Vec<Cursor>,
+) {
+    for child in periph.children(cluster, sort) {
+        match child {
+            Child::Cluster(c) => {
+                rows.push(Cursor::Cluster(p, c));
+                if periph.clusters[c].expanded {
+                    visible_children(periph, p, Some(c), sort, rows);
+                }
+            }
+            Child::Reg(r) => {
+                rows.push(Cursor::Reg(p, r));
+                if periph.regs[r].expanded {
+                    rows.extend((0..periph.regs[r].fields.len()).map(|f| Cursor::Field(p, r, f)));
+                }
+            }
+        }
+    }
+}
+
+/// Position `delta` rows away from `cursor`, stopping at the ends of the tree.
+///
+/// A cursor hidden by collapsing one of its parents moves from that parent.
+pub fn step(
+    peripherals: &[Peripheral],
+    sort: Sort,
+    cursor: Option<Cursor>,
+    delta: isize,
+) -> Option<Cursor> {
+    let rows = visible(peripherals, sort);
+    let index = std::iter::successors(cursor, |cursor| cursor.parent(peripherals))
+        .find_map(|cursor| rows.iter().position(|row| *row == cursor));
+    let Some(index) = index else {
+        return rows.first().copied();
+    };
+    let index = index.saturating_add_signed(delta).min(rows.len() - 1);
+    rows.get(index).copied()
+}
+
+/// Field `delta` fields away within the register at `cursor`, wrapping around.
+pub fn cycle_field(peripherals: &[Peripheral], cursor: Cursor, delta: isize) -> Option<Cursor> {
+    let (p, r, current) = match cursor {
+        Cursor::Peripheral(_) | Cursor::Cluster(..) => return None,
+        Cursor::Reg(p, r) => (p, r, None),
+        Cursor::Field(p, r, f) => (p, r, Some(f)),
+    };
+    let count = peripherals[p].regs[r].fields.len();
+    if count == 0 {
+        return None;
+    }
+    let next = match current {
+        Some(f) => (f as isize + delta).rem_euclid(count as isize) as usize,
+        None if delta < 0 => count - 1,
+        None => 0,
+    };
+    Some(Cursor::Field(p, r, next))
+}
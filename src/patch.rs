@@ -0,0 +1,618 @@
+//! svdtools style patch files, fixing vendor register descriptions locally
+//! without editing them. A patch is applied to the model before the tree is
+//! built:
+//!
+//! ```yaml
+//! _delete: [DBG]
+//! _derive:
+//!   TIMER2: { _from: TIMER0, baseAddress: 0x40002000 }
+//! _add:
+//!   WDT:
+//!     baseAddress: 0x40003000
+//!     registers:
+//!       CTRL:
+//!         addressOffset: 0x0
+//!         fields: { EN: { bitOffset: 0, bitWidth: 1 } }
+//! TIMER*:
+//!   _modify:
+//!     CR: { resetValue: 0x0001 }
+//!   CR:
+//!     _modify:
+//!       MODE: { bitRange: "[2:1]" }
+//!     MODE:
+//!       Continuous: [0, "Run continuously"]
+//!       Single: [1, "Stop at the match"]
+//!     PSC: [0, 7]
+//! ```
+//!
+//! At each level `_delete` runs first, then `_modify`, `_add` and `_derive`,
+//! then the specs of the matching children. Within a peripheral, `_cluster`
+//! then groups the matching registers not yet in a cluster into a new one,
+//! prefixing their names with its name. Names may be globs with `*`, `?` and
+//! `[...]`, several separated by commas. The specs take the SVD element
+//! names: `name`, `description`, `baseAddress`, `addressOffset`, `resetValue`,
+//! `access`, and `bitOffset` with `bitWidth` or `bitRange`. Under a field, a
+//! map replaces its enumerated values and a `[min, max]` list sets a write
+//! constraint.
+
+use std::fs;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::model::{
+    parse_number, Access, Cluster, Device, EnumValue, Field, ParseError, Peripheral, Register,
+    WriteConstraint, REG_BITS,
+};
+
+/// Applies the patch file at `path`.
+pub fn load(path: &str, device: &mut Device) -> Result<(), ParseError> {
+    apply(device, &fs::read_to_string(path)?)
+}
+
+pub fn apply(device: &mut Device, src: &str) -> Result<(), ParseError> {
+    let patch: Mapping = serde_yaml::from_str(src)?;
+    check_commands(&patch, &["_svd", "_delete", "_modify", "_add", "_derive"])?;
+
+    for pattern in names(patch.get("_delete"))? {
+        device
+            .peripherals
+            .retain(|periph| !matches(pattern, &periph.name));
+    }
+    for (pattern, spec) in entries(patch.get("_modify"))? {
+        let spec = mapping(spec, pattern)?;
+        for periph in device
+            .peripherals
+            .iter_mut()
+            .filter(|periph| matches(pattern, &periph.name))
+        {
+            modify_peripheral(periph, spec)?;
+        }
+    }
+    for (name, spec) in entries(patch.get("_add"))? {
+        if device.peripheral(name).is_some() {
+            return Err(format!("peripheral {} already exists", name).into());
+        }
+        let spec = mapping(spec, name)?;
+        let mut periph = Peripheral {
+            name: name.to_string(),
+            description: None,
+            base_address: 0,
+            registers: vec![],
+            clusters: vec![],
+        };
+        modify_peripheral(&mut periph, spec)?;
+        for (name, spec) in entries(spec.get("registers"))? {
+            add_register(&mut periph, name, spec)?;
+        }
+        device.peripherals.push(periph);
+    }
+    for (name, spec) in entries(patch.get("_derive"))? {
+        let (from, spec) = derive_spec(name, spec)?;
+        let mut periph = device
+            .peripheral(from)
+            .ok_or_else(|| format!("{}: unknown peripheral {}", name, from))?
+            .clone();
+        periph.name = name.to_string();
+        if let Some(spec) = spec {
+            modify_peripheral(&mut periph, spec)?;
+        }
+        match device.peripherals.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = periph,
+            None => device.peripherals.push(periph),
+        }
+    }
+
+    for (pattern, spec) in children(&patch)? {
+        let spec = mapping(spec, pattern)?;
+        let mut matched = false;
+        for periph in device
+            .peripherals
+            .iter_mut()
+            .filter(|periph| matches(pattern, &periph.name))
+        {
+            matched = true;
+            patch_peripheral(periph, spec).map_err(|err| format!("{}: {}", periph.name, err))?;
+        }
+        if !matched {
+            return Err(format!("no peripheral matches '{}'", pattern).into());
+        }
+    }
+    Ok(())
+}
+
+fn patch_peripheral(periph: &mut Peripheral, patch: &Mapping) -> Result<(), ParseError> {
+    check_commands(
+        patch,
+        &["_delete", "_modify", "_add", "_derive", "_cluster"],
+    )?;
+
+    for pattern in names(patch.get("_delete"))? {
+        periph.registers.retain(|reg| !matches(pattern, &reg.name));
+    }
+    remove_empty_clusters(periph);
+    for (pattern, spec) in entries(patch.get("_modify"))? {
+        let spec = mapping(spec, pattern)?;
+        let base_address = periph.base_address;
+        let clusters = &periph.clusters;
+        for reg in periph
+            .registers
+            .iter_mut()
+            .filter(|reg| matches(pattern, &reg.name))
+        {
+            let base = reg.cluster.map_or(base_address, |c| clusters[c].address);
+            modify_register(reg, base, spec)?;
+        }
+    }
+    for (name, spec) in entries(patch.get("_add"))? {
+        add_register(periph, name, spec)?;
+    }
+    for (name, spec) in entries(patch.get("_derive"))? {
+        let (from, spec) = derive_spec(name, spec)?;
+        let mut reg = periph
+            .register(from)
+            .ok_or_else(|| format!("{}: unknown register {}", name, from))?
+            .clone();
+        reg.name = name.to_string();
+        if let Some(spec) = spec {
+            let base = reg
+                .cluster
+                .map_or(periph.base_address, |c| periph.clusters[c].address);
+            modify_register(&mut reg, base, spec)?;
+        }
+        periph.registers.push(reg);
+    }
+    for (name, spec) in entries(patch.get("_cluster"))? {
+        add_cluster(periph, name, mapping(spec, name)?)?;
+    }
+
+    for (pattern, spec) in children(patch)? {
+        let spec = mapping(spec, pattern)?;
+        let mut matched = false;
+        for reg in periph
+            .registers
+            .iter_mut()
+            .filter(|reg| matches(pattern, &reg.name))
+        {
+            matched = true;
+            patch_register(reg, spec).map_err(|err| format!("{}: {}", reg.name, err))?;
+        }
+        if !matched {
+            return Err(format!("no register matches '{}'", pattern).into());
+        }
+    }
+    Ok(())
+}
+
+fn patch_register(reg: &mut Register, patch: &Mapping) -> Result<(), ParseError> {
+    check_commands(patch, &["_delete", "_modify", "_add"])?;
+
+    for pattern in names(patch.get("_delete"))? {
+        reg.fields.retain(|field| !matches(pattern, &field.name));
+    }
+    for (pattern, spec) in entries(patch.get("_modify"))? {
+        let spec = mapping(spec, pattern)?;
+        for field in reg
+            .fields
+            .iter_mut()
+            .filter(|field| matches(pattern, &field.name))
+        {
+            modify_field(field, spec)?;
+        }
+    }
+    for (name, spec) in entries(patch.get("_add"))? {
+        add_field(reg, name, spec)?;
+    }
+
+    for (pattern, spec) in children(patch)? {
+        let mut matched = false;
+        for field in reg
+            .fields
+            .iter_mut()
+            .filter(|field| matches(pattern, &field.name))
+        {
+            matched = true;
+            patch_field(field, spec).map_err(|err| format!("{}: {}", field.name, err))?;
+        }
+        if !matched {
+            return Err(format!("no field matches '{}'", pattern).into());
+        }
+    }
+    Ok(())
+}
+
+/// Sets the enumerated values of a field from `Name: [value, "description"]`
+/// entries, or its write constraint from a `[min, max]` list.
+fn patch_field(field: &mut Field, patch: &Value) -> Result<(), ParseError> {
+    if let Some([min, max]) = patch.as_sequence().map(Vec::as_slice) {
+        let (min, max) = (number(min, "min")?, number(max, "max")?);
+        if min > max || max > field.max_value() as u64 {
+            return Err(format!("invalid range [{}, {}] for the field", min, max).into());
+        }
+        field.write_constraint = Some(WriteConstraint::Range {
+            min: min as u16,
+            max: max as u16,
+        });
+        return Ok(());
+    }
+    let mut enum_values = vec![];
+    for (name, spec) in entries(Some(patch))? {
+        let (value, description) = match spec {
+            Value::Sequence(items) => match items.as_slice() {
+                [value] => (value, None),
+                [value, description] => (value, Some(string(description, name)?)),
+                _ => return Err(format!("{}: expected [value, description]", name).into()),
+            },
+            value => (value, None),
+        };
+        let value = number(value, name)?;
+        if value > field.max_value() as u64 {
+            return Err(format!("{}: value {} too wide for the field", name, value).into());
+        }
+        enum_values.push(EnumValue {
+            name: name.to_string(),
+            description,
+            value: value as u16,
+        });
+    }
+    field.enum_values = enum_values;
+    Ok(())
+}
+
+fn modify_peripheral(periph: &mut Peripheral, spec: &Mapping) -> Result<(), ParseError> {
+    for (key, value) in spec {
+        match self::key(key)? {
+            "name" => periph.name = string(value, "name")?,
+            "description" => periph.description = Some(string(value, "description")?),
+            "baseAddress" => {
+                let base_address = u32::try_from(number(value, "baseAddress")?)
+                    .map_err(|_| format!("{}: baseAddress too large", periph.name))?;
+                for reg in periph.registers.iter_mut() {
+                    reg.address = reg
+                        .address
+                        .wrapping_sub(periph.base_address)
+                        .wrapping_add(base_address);
+                }
+                for cluster in periph.clusters.iter_mut() {
+                    cluster.address = cluster
+                        .address
+                        .wrapping_sub(periph.base_address)
+                        .wrapping_add(base_address);
+                }
+                periph.base_address = base_address;
+            }
+            // Handled by `_add` and `_derive`
+            "registers" | "_from" => (),
+            key => {
+                return Err(format!("{}: unknown peripheral property {}", periph.name, key).into())
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets the properties in `spec`, `addressOffset` being relative to
+/// `base_address`, that of the peripheral or the enclosing cluster.
+fn modify_register(
+    reg: &mut Register,
+    base_address: u32,
+    spec: &Mapping,
+) -> Result<(), ParseError> {
+    for (key, value) in spec {
+        match self::key(key)? {
+            "name" => reg.name = string(value, "name")?,
+            "description" => reg.description = Some(string(value, "description")?),
+            "addressOffset" => {
+                let offset = number(value, "addressOffset")?;
+                reg.address = u32::try_from(offset)
+                    .ok()
+                    .and_then(|offset| base_address.checked_add(offset))
+                    .ok_or_else(|| format!("{}: addressOffset too large", reg.name))?;
+            }
+            "resetValue" => {
+                let reset = number(value, "resetValue")?;
+                reg.reset_value = u16::try_from(reset)
+                    .map_err(|_| format!("{}: resetValue 0x{:X} too wide", reg.name, reset))?;
+            }
+            "access" => {
+                let access = access(value)?;
+                for field in reg.fields.iter_mut() {
+                    field.access = access;
+                }
+            }
+            // Handled by `_add` and `_derive`
+            "fields" | "_from" => (),
+            key => return Err(format!("{}: unknown register property {}", reg.name, key).into()),
+        }
+    }
+    Ok(())
+}
+
+fn modify_field(field: &mut Field, spec: &Mapping) -> Result<(), ParseError> {
+    for (key, value) in spec {
+        match self::key(key)? {
+            "name" => field.name = string(value, "name")?,
+            "description" => field.description = Some(string(value, "description")?),
+            "bitOffset" => field.offset = bits(value, "bitOffset")?,
+            "bitWidth" => field.width = bits(value, "bitWidth")?,
+            "bitRange" => {
+                let range = string(value, "bitRange")?;
+                let (msb, lsb) = range
+                    .trim_matches(|c| c == '[' || c == ']')
+                    .split_once(':')
+                    .and_then(|(msb, lsb)| Some((parse_number(msb)?, parse_number(lsb)?)))
+                    .filter(|(msb, lsb)| msb >= lsb && *msb < REG_BITS as u64)
+                    .ok_or_else(|| format!("{}: invalid bitRange '{}'", field.name, range))?;
+                field.offset = lsb as u8;
+                field.width = (msb - lsb + 1) as u8;
+            }
+            "access" => field.access = access(value)?,
+            key => return Err(format!("{}: unknown field property {}", field.name, key).into()),
+        }
+    }
+    if field.width == 0 || field.offset as u32 + field.width as u32 > REG_BITS as u32 {
+        return Err(format!("{}: bits beyond the register width", field.name).into());
+    }
+    Ok(())
+}
+
+/// Removes the clusters left without registers or nested clusters, e.g. by
+/// `_delete`, renumbering the remaining ones.
+fn remove_empty_clusters(periph: &mut Peripheral) {
+    while let Some(empty) = (0..periph.clusters.len()).rev().find(|&c| {
+        !periph.registers.iter().any(|reg| reg.cluster == Some(c))
+            && !periph
+                .clusters
+                .iter()
+                .any(|cluster| cluster.parent == Some(c))
+    }) {
+        periph.clusters.remove(empty);
+        let renumber = |index: &mut Option<usize>| {
+            if let Some(i) = index.as_mut().filter(|i| **i > empty) {
+                *i -= 1;
+            }
+        };
+        for reg in periph.registers.iter_mut() {
+            renumber(&mut reg.cluster);
+        }
+        for cluster in periph.clusters.iter_mut() {
+            renumber(&mut cluster.parent);
+        }
+    }
+}
+
+/// Groups the registers matching the keys of `spec` into a new top level
+/// cluster, `description` setting its description. Only registers outside of
+/// any cluster are grouped, those in one keep it and its name prefix.
+fn add_cluster(periph: &mut Peripheral, name: &str, spec: &Mapping) -> Result<(), ParseError> {
+    let mut description = None;
+    let mut members = vec![];
+    for (key, value) in entries_of(spec)? {
+        match key {
+            "description" => description = Some(string(value, "description")?),
+            pattern => members.extend(
+                periph
+                    .registers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, reg)| reg.cluster.is_none() && matches(pattern, &reg.name))
+                    .map(|(r, _)| r),
+            ),
+        }
+    }
+    members.sort_unstable();
+    members.dedup();
+    let Some(address) = members.iter().map(|&r| periph.registers[r].address).min() else {
+        return Err(format!("no register matches cluster {}", name).into());
+    };
+    periph.clusters.push(Cluster {
+        name: name.to_string(),
+        description,
+        address,
+        parent: None,
+    });
+    let index = periph.clusters.len() - 1;
+    for r in members {
+        let reg = &mut periph.registers[r];
+        reg.name = format!("{}_{}", name, reg.name);
+        reg.cluster = Some(index);
+    }
+    Ok(())
+}
+
+fn add_register(periph: &mut Peripheral, name: &str, spec: &Value) -> Result<(), ParseError> {
+    if periph.register(name).is_some() {
+        return Err(format!("register {} already exists", name).into());
+    }
+    let spec = mapping(spec, name)?;
+    let mut reg = Register {
+        name: name.to_string(),
+        description: None,
+        address: periph.base_address,
+        reset_value: 0,
+        read_side_effects: false,
+        fields: vec![],
+        cluster: None,
+    };
+    for (name, spec) in entries(spec.get("fields"))? {
+        add_field(&mut reg, name, spec)?;
+    }
+    modify_register(&mut reg, periph.base_address, spec)?;
+    periph.registers.push(reg);
+    Ok(())
+}
+
+fn add_field(reg: &mut Register, name: &str, spec: &Value) -> Result<(), ParseError> {
+    if reg.field(name).is_some() {
+        return Err(format!("field {} already exists", name).into());
+    }
+    let mut field = Field {
+        name: name.to_string(),
+        description: None,
+        offset: 0,
+        width: 1,
+        access: Access::default(),
+        write_constraint: None,
+        enum_values: vec![],
+    };
+    modify_field(&mut field, mapping(spec, name)?)?;
+    reg.fields.push(field);
+    Ok(())
+}
+
+/// Source of a `_derive` entry, `NEW: FROM` or `NEW: { _from: FROM, ... }` with
+/// properties to modify.
+fn derive_spec<'a>(
+    name: &str,
+    spec: &'a Value,
+) -> Result<(&'a str, Option<&'a Mapping>), ParseError> {
+    match spec {
+        Value::String(from) => Ok((from.as_str(), None)),
+        _ => {
+            let spec = mapping(spec, name)?;
+            let from = spec
+                .get("_from")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("{}: missing _from", name))?;
+            Ok((from, Some(spec)))
+        }
+    }
+}
+
+/// Rejects the commands starting with `_` not in `known`.
+fn check_commands(patch: &Mapping, known: &[&str]) -> Result<(), ParseError> {
+    for key in patch.keys() {
+        let key = self::key(key)?;
+        if key.starts_with('_') && !known.contains(&key) {
+            return Err(format!("unknown command {}", key).into());
+        }
+    }
+    Ok(())
+}
+
+/// Entries not starting with `_`, patching the matching children.
+fn children(patch: &Mapping) -> Result<Vec<(&str, &Value)>, ParseError> {
+    Ok(entries_of(patch)?
+        .into_iter()
+        .filter(|(key, _)| !key.starts_with('_'))
+        .collect())
+}
+
+fn entries(value: Option<&Value>) -> Result<Vec<(&str, &Value)>, ParseError> {
+    match value {
+        Some(value) => entries_of(mapping(value, "entry")?),
+        None => Ok(vec![]),
+    }
+}
+
+fn entries_of(mapping: &Mapping) -> Result<Vec<(&str, &Value)>, ParseError> {
+    mapping
+        .iter()
+        .map(|(key, value)| Ok((self::key(key)?, value)))
+        .collect()
+}
+
+/// The `_delete` list, a single name or a list of them.
+fn names(value: Option<&Value>) -> Result<Vec<&str>, ParseError> {
+    match value {
+        None => Ok(vec![]),
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .map(|item| string_ref(item, "_delete"))
+            .collect(),
+        Some(value) => Ok(vec![string_ref(value, "_delete")?]),
+    }
+}
+
+fn key(value: &Value) -> Result<&str, ParseError> {
+    string_ref(value, "key")
+}
+
+fn mapping<'a>(value: &'a Value, what: &str) -> Result<&'a Mapping, ParseError> {
+    value
+        .as_mapping()
+        .ok_or_else(|| format!("{}: expected a map", what).into())
+}
+
+fn string_ref<'a>(value: &'a Value, what: &str) -> Result<&'a str, ParseError> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("{}: expected a string", what).into())
+}
+
+fn string(value: &Value, what: &str) -> Result<String, ParseError> {
+    Ok(string_ref(value, what)?
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+fn number(value: &Value, what: &str) -> Result<u64, ParseError> {
+    let number = match value {
+        Value::Number(number) => number.as_u64(),
+        Value::String(text) => parse_number(text.trim()),
+        _ => None,
+    };
+    number.ok_or_else(|| format!("{}: invalid number {:?}", what, value).into())
+}
+
+/// A bit offset or width, at most the register width.
+fn bits(value: &Value, what: &str) -> Result<u8, ParseError> {
+    let bits = number(value, what)?;
+    if bits > REG_BITS as u64 {
+        return Err(format!("{} {} beyond the register width", what, bits).into());
+    }
+    Ok(bits as u8)
+}
+
+fn access(value: &Value) -> Result<Access, ParseError> {
+    match string_ref(value, "access")? {
+        "read-write" | "read-writeOnce" => Ok(Access::ReadWrite),
+        "read-only" => Ok(Access::ReadOnly),
+        "write-only" | "writeOnce" => Ok(Access::WriteOnly),
+        access => Err(format!("unknown access '{}'", access).into()),
+    }
+}
+
+/// Whether `name` matches one of the comma separated globs of `pattern`.
+fn matches(pattern: &str, name: &str) -> bool {
+    pattern
+        .split(',')
+        .any(|glob| glob_matches(glob.trim().as_bytes(), name.as_bytes()))
+}
+
+fn glob_matches(glob: &[u8], name: &[u8]) -> bool {
+    match glob.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| glob_matches(rest, &name[i..])),
+        Some((b'?', rest)) => !name.is_empty() && glob_matches(rest, &name[1..]),
+        Some((b'[', rest)) if rest.contains(&b']') => {
+            let end = rest.iter().position(|&c| c == b']').unwrap_or_default();
+            match name.split_first() {
+                Some((&c, name)) => {
+                    in_class(&rest[..end], c) && glob_matches(&rest[end + 1..], name)
+                }
+                None => false,
+            }
+        }
+        Some((c, rest)) => name.first() == Some(c) && glob_matches(rest, &name[1..]),
+    }
+}
+
+/// Whether `c` is one of the `[...]` characters or ranges like `0-9`.
+fn in_class(class: &[u8], c: u8) -> bool {
+    let mut i = 0;
+    while i < class.len() {
+        if class.get(i + 1) == Some(&b'-') && i + 2 < class.len() {
+            if (class[i]..=class[i + 2]).contains(&c) {
+                return true;
+            }
+            i += 3;
+        } else {
+            if class[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
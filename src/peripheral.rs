@@ -0,0 +1,220 @@
+use iced::widget::{button, column, row, text, Button};
+use iced::{padding, Element, Renderer, Theme};
+
+use crate::model;
+use crate::reg16;
+use crate::reg16::{described, selected, Reg16};
+
+pub struct Peripheral {
+    pub name: String,
+    pub description: Option<String>,
+    pub base_address: u32,
+    pub expanded: bool,
+    /// Whether the keyboard cursor is on the peripheral header.
+    pub selected: bool,
+    pub regs: Vec<Reg16>,
+    /// Clusters grouping the registers, indexed like in the model.
+    pub clusters: Vec<Cluster>,
+}
+
+/// A cluster of registers, an expandable node between the peripheral and its
+/// registers.
+pub struct Cluster {
+    pub model: model::Cluster,
+    /// Name shown in the tree, without the prefix of the enclosing cluster.
+    pub label: String,
+    pub expanded: bool,
+    /// Whether the keyboard cursor is on the cluster header.
+    pub selected: bool,
+}
+
+/// Order of the peripherals and of the registers and clusters within them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Sort {
+    #[default]
+    Address,
+    Name,
+}
+
+/// Indices of the peripherals in display order.
+pub fn order(peripherals: &[Peripheral], sort: Sort) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..peripherals.len()).collect();
+    match sort {
+        Sort::Address => order.sort_by_key(|&p| peripherals[p].base_address),
+        Sort::Name => order.sort_by(|&a, &b| peripherals[a].name.cmp(&peripherals[b].name)),
+    }
+    order
+}
+
+/// A node directly in the peripheral or a cluster.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Child {
+    Cluster(usize),
+    Reg(usize),
+}
+
+pub enum Action {
+    None,
+    ReadAll,
+    Reg(usize, reg16::Action),
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    ToggleExpand,
+    ToggleCluster(usize),
+    ReadAll,
+    Reg(usize, reg16::Message),
+}
+
+impl Peripheral {
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::ToggleExpand => {
+                self.expanded = !self.expanded;
+                Action::None
+            }
+            Message::ToggleCluster(index) => {
+                let cluster = &mut self.clusters[index];
+                cluster.expanded = !cluster.expanded;
+                Action::None
+            }
+            Message::ReadAll => Action::ReadAll,
+            Message::Reg(index, message) => Action::Reg(index, self.regs[index].update(message)),
+        }
+    }
+
+    /// Clusters and registers directly in `cluster`, or in the peripheral if
+    /// `None`, in display order.
+    pub fn children(&self, cluster: Option<usize>, sort: Sort) -> Vec<Child> {
+        let mut children = vec![];
+        for (r, reg) in self.regs.iter().enumerate() {
+            let child = if reg.model.cluster == cluster {
+                Child::Reg(r)
+            } else {
+                // The registers of a cluster follow each other, the first one
+                // places it
+                match self.ancestor_in(reg.model.cluster, cluster) {
+                    Some(c) => Child::Cluster(c),
+                    None => continue,
+                }
+            };
+            if !children.contains(&child) {
+                children.push(child);
+            }
+        }
+        for (c, candidate) in self.clusters.iter().enumerate() {
+            if candidate.model.parent == cluster && !children.contains(&Child::Cluster(c)) {
+                children.push(Child::Cluster(c));
+            }
+        }
+        // Stable, ties stay in description order
+        match sort {
+            Sort::Address => children.sort_by_key(|child| match *child {
+                Child::Cluster(c) => self.clusters[c].model.address,
+                Child::Reg(r) => self.regs[r].model.address,
+            }),
+            Sort::Name => children.sort_by(|a, b| self.label(*a).cmp(self.label(*b))),
+        }
+        children
+    }
+
+    fn label(&self, child: Child) -> &str {
+        match child {
+            Child::Cluster(c) => self.clusters[c].label.as_str(),
+            Child::Reg(r) => self.regs[r].label.as_str(),
+        }
+    }
+
+    /// The cluster enclosing `cluster` that is directly in `parent`.
+    fn ancestor_in(&self, mut cluster: Option<usize>, parent: Option<usize>) -> Option<usize> {
+        while let Some(index) = cluster {
+            let up = self.clusters[index].model.parent;
+            if up == parent {
+                return Some(index);
+            }
+            cluster = up;
+        }
+        None
+    }
+
+    pub fn view(&self, sort: Sort) -> Element<'_, Message, Theme, Renderer> {
+        let but_text = if self.expanded { "-" } else { "+" };
+        let header = row![
+            text_button(but_text).on_press(Message::ToggleExpand),
+            text(format!("0x{:08X}", self.base_address)),
+            described(
+                text_button(self.name.as_str())
+                    .style(if self.selected {
+                        selected
+                    } else {
+                        button::text
+                    })
+                    .on_press(Message::ToggleExpand),
+                self.description.as_deref()
+            ),
+            button(text("Read all"))
+                .padding([0, 5])
+                .on_press(Message::ReadAll),
+        ]
+        .spacing(10);
+        let mut periph = column![header].spacing(20);
+        if self.expanded {
+            periph = periph.push(self.children_view(None, sort));
+        }
+        periph.into()
+    }
+
+    fn children_view(
+        &self,
+        cluster: Option<usize>,
+        sort: Sort,
+    ) -> Element<'_, Message, Theme, Renderer> {
+        column(self.children(cluster, sort).into_iter().map(|child| {
+            match child {
+                Child::Cluster(c) => self.cluster_view(c, sort),
+                Child::Reg(r) => self.regs[r]
+                    .view()
+                    .map(move |message| Message::Reg(r, message)),
+            }
+        }))
+        .padding(padding::left(20))
+        .spacing(20)
+        .into()
+    }
+
+    fn cluster_view(&self, index: usize, sort: Sort) -> Element<'_, Message, Theme, Renderer> {
+        let cluster = &self.clusters[index];
+        let but_text = if cluster.expanded { "-" } else { "+" };
+        let header = row![
+            text_button(but_text).on_press(Message::ToggleCluster(index)),
+            text(format!(
+                "0x{:08X} +0x{:04X}",
+                cluster.model.address,
+                cluster.model.address.wrapping_sub(self.base_address)
+            )),
+            described(
+                text_button(cluster.label.as_str())
+                    .style(if cluster.selected {
+                        selected
+                    } else {
+                        button::text
+                    })
+                    .on_press(Message::ToggleCluster(index)),
+                cluster.model.description.as_deref()
+            ),
+        ]
+        .spacing(10);
+        let mut node = column![header].spacing(20);
+        if cluster.expanded {
+            node = node.push(self.children_view(Some(index), sort));
+        }
+        node.into()
+    }
+}
+
+fn text_button<'a>(
+    content: impl Into<Element<'a, Message, Theme, Renderer>>,
+) -> Button<'a, Message> {
+    button(content).style(button::text).padding(0)
+}
@@ -0,0 +1,735 @@
+//! Import of register descriptions written in SystemRDL 2.0.
+//!
+//! The subset used for register blocks is understood: `addrmap`, `regfile`,
+//! `reg`, `field` and `enum` definitions, named or anonymous, their instances
+//! with arrays, bit ranges, resets and `@`/`+=` addresses, and the `name`,
+//! `desc`, `sw`, `reset`, `encode`, `onread`, `rclr`, `regwidth` and `fieldwidth`
+//! properties, also as `inst.child->prop` dynamic assignments. Other properties
+//! are ignored. Registers are at most 16 bits wide: `regwidth` defaults to 32
+//! and must be set to 16 or less, e.g. with `default regwidth = 16;`, and fields
+//! past bit 15 are rejected.
+//!
+//! Every `addrmap` instantiated in the top one becomes a peripheral, registers
+//! placed directly in the top one make a peripheral named after it. Register
+//! files are flattened into their parent, prefixing the register names.
+
+use std::collections::HashMap;
+
+use crate::model::{
+    parse_number, Access, Device, EnumValue, Field, ParseError, Peripheral, Register, REG_BITS,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Str(String),
+    Punct(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(name) => f.write_str(name),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Punct(p) => f.write_str(p),
+        }
+    }
+}
+
+const PUNCTS: [&str; 13] = [
+    "+=", "%=", "->", "{", "}", ";", "=", "@", "[", "]", ":", ",", ".",
+];
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut rest = src;
+    while let Some(c) = rest.chars().next() {
+        if c == '\n' {
+            line += 1;
+            rest = &rest[1..];
+        } else if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if rest.starts_with("//") {
+            rest = rest.find('\n').map_or("", |end| &rest[end..]);
+        } else if rest.starts_with("/*") {
+            let end = rest
+                .find("*/")
+                .ok_or_else(|| error(line, "unterminated comment"))?;
+            line += rest[..end].matches('\n').count();
+            rest = &rest[end + 2..];
+        } else if c == '"' {
+            let end = rest[1..]
+                .find('"')
+                .ok_or_else(|| error(line, "unterminated string"))?;
+            let text = &rest[1..end + 1];
+            line += text.matches('\n').count();
+            tokens.push((Token::Str(text.to_string()), line));
+            rest = &rest[end + 2..];
+        } else if c.is_ascii_digit() || c == '\'' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '\''))
+                .unwrap_or(rest.len());
+            let number = parse_number(&rest[..end])
+                .ok_or_else(|| error(line, format!("invalid number '{}'", &rest[..end])))?;
+            tokens.push((Token::Number(number), line));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push((Token::Ident(rest[..end].to_string()), line));
+            rest = &rest[end..];
+        } else if let Some(punct) = PUNCTS.iter().find(|p| rest.starts_with(*p)) {
+            tokens.push((Token::Punct(punct), line));
+            rest = &rest[punct.len()..];
+        } else {
+            return Err(error(line, format!("unexpected character '{}'", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+fn error(line: usize, message: impl std::fmt::Display) -> ParseError {
+    format!("line {}: {}", line, message).into()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Addrmap,
+    Regfile,
+    Reg,
+    Field,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(u64),
+    Str(String),
+    Ident(String),
+}
+
+/// A component definition with the properties assigned in it.
+#[derive(Debug, Clone)]
+struct Component {
+    kind: Kind,
+    props: HashMap<String, Value>,
+    /// Values of `default` assignments, for the nested components.
+    defaults: HashMap<String, Value>,
+    instances: Vec<Instance>,
+}
+
+#[derive(Debug, Clone)]
+struct Instance {
+    name: String,
+    component: Component,
+    /// Array dimensions, or the field width for `name[width]` fields.
+    dims: Vec<u64>,
+    /// Field bit range as written, `[msb:lsb]`.
+    range: Option<(u64, u64)>,
+    reset: Option<u64>,
+    address: Option<u64>,
+    stride: Option<u64>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    types: HashMap<String, Component>,
+    enums: HashMap<String, Vec<EnumValue>>,
+    /// Names of the `addrmap` types instantiated somewhere.
+    instantiated: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(0, |(_, line)| *line)
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| error(self.line(), "unexpected end of file"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, punct: &'static str) -> bool {
+        if self.peek() == Some(&Token::Punct(punct)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &'static str) -> Result<(), ParseError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected(punct))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(token) => error(
+                self.line(),
+                format!("expected {}, found '{}'", expected, token),
+            ),
+            None => error(self.line(), format!("expected {}", expected)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("a name"))
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, ParseError> {
+        match self.next()? {
+            Token::Number(n) => Ok(n),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("a number"))
+            }
+        }
+    }
+
+    /// Parses the items of a component body up to `}`, or of the file.
+    fn body(&mut self, component: &mut Component) -> Result<(), ParseError> {
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::Punct("}") => return Ok(()),
+                Token::Ident(word) => self.item(component, &word)?,
+                _ => return Err(self.unexpected("a definition or property")),
+            }
+        }
+        Ok(())
+    }
+
+    fn item(&mut self, parent: &mut Component, word: &str) -> Result<(), ParseError> {
+        let kind = match word {
+            "addrmap" => Some(Kind::Addrmap),
+            "regfile" => Some(Kind::Regfile),
+            "reg" => Some(Kind::Reg),
+            "field" => Some(Kind::Field),
+            _ => None,
+        };
+        self.pos += 1;
+        if word == "enum" {
+            return self.enum_definition();
+        }
+        if word == "default" {
+            let name = self.ident()?;
+            let value = self.property_value()?;
+            parent.defaults.insert(name, value);
+            return Ok(());
+        }
+        if let Some(kind) = kind {
+            let name = match self.peek() {
+                Some(Token::Ident(name)) => {
+                    let name = name.clone();
+                    self.pos += 1;
+                    Some(name)
+                }
+                _ => None,
+            };
+            self.expect("{")?;
+            let mut component = Component {
+                kind,
+                props: inherited(parent, kind),
+                defaults: parent.defaults.clone(),
+                instances: vec![],
+            };
+            self.body(&mut component)?;
+            self.expect("}")?;
+            if let Some(name) = name {
+                self.types.insert(name.clone(), component.clone());
+                if self.eat(";") {
+                    return Ok(());
+                }
+            }
+            return self.instances(parent, component);
+        }
+        if let Some(component) = self.types.get(word).cloned() {
+            if component.kind == Kind::Addrmap {
+                self.instantiated.push(word.to_string());
+            }
+            return self.instances(parent, component);
+        }
+        if matches!(self.peek(), Some(Token::Punct("." | "->"))) {
+            return self.dynamic_assignment(parent, word);
+        }
+        let value = self.property_value()?;
+        parent.props.insert(word.to_string(), value);
+        Ok(())
+    }
+
+    /// Parses the rest of `inst.child->prop = value;`, assigning the property
+    /// to the instance at that path in `parent`.
+    fn dynamic_assignment(
+        &mut self,
+        parent: &mut Component,
+        first: &str,
+    ) -> Result<(), ParseError> {
+        let line = self.line();
+        let mut path = vec![first.to_string()];
+        while self.eat(".") {
+            path.push(self.ident()?);
+        }
+        self.expect("->")?;
+        let prop = self.ident()?;
+        let value = self.property_value()?;
+        let missing = || error(line, format!("no instance {}", path.join(".")));
+        let (last, outer) = path.split_last().ok_or_else(missing)?;
+        let mut component = parent;
+        for name in outer {
+            component = &mut instance_mut(component, name).ok_or_else(missing)?.component;
+        }
+        let instance = instance_mut(component, last).ok_or_else(missing)?;
+        if prop == "reset" {
+            instance.reset = None;
+        }
+        instance.component.props.insert(prop, value);
+        Ok(())
+    }
+
+    /// Parses `= value;` or `;`, the latter setting a boolean property.
+    fn property_value(&mut self) -> Result<Value, ParseError> {
+        if self.eat(";") {
+            return Ok(Value::Ident("true".to_string()));
+        }
+        self.expect("=")?;
+        let value = match self.next()? {
+            Token::Number(n) => Value::Number(n),
+            Token::Str(s) => Value::Str(s),
+            Token::Ident(name) => Value::Ident(name),
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected("a value"));
+            }
+        };
+        self.expect(";")?;
+        Ok(value)
+    }
+
+    fn instances(
+        &mut self,
+        parent: &mut Component,
+        component: Component,
+    ) -> Result<(), ParseError> {
+        loop {
+            let name = self.ident()?;
+            let mut instance = Instance {
+                name,
+                component: component.clone(),
+                dims: vec![],
+                range: None,
+                reset: None,
+                address: None,
+                stride: None,
+            };
+            while self.eat("[") {
+                let first = self.number()?;
+                if self.eat(":") {
+                    instance.range = Some((first, self.number()?));
+                } else {
+                    instance.dims.push(first);
+                }
+                self.expect("]")?;
+            }
+            if self.eat("=") {
+                instance.reset = Some(self.number()?);
+            }
+            if self.eat("@") {
+                instance.address = Some(self.number()?);
+            }
+            if self.eat("+=") {
+                instance.stride = Some(self.number()?);
+            }
+            if self.eat("%=") {
+                self.number()?;
+            }
+            parent.instances.push(instance);
+            if !self.eat(",") {
+                return self.expect(";");
+            }
+        }
+    }
+
+    fn enum_definition(&mut self) -> Result<(), ParseError> {
+        let name = self.ident()?;
+        self.expect("{")?;
+        let mut values = vec![];
+        let mut next = 0;
+        while !self.eat("}") {
+            let entry = self.ident()?;
+            if self.eat("=") {
+                next = self.number()?;
+            }
+            let mut description = None;
+            if self.eat("{") {
+                let mut props = Component {
+                    kind: Kind::Field,
+                    props: HashMap::new(),
+                    defaults: HashMap::new(),
+                    instances: vec![],
+                };
+                self.body(&mut props)?;
+                self.expect("}")?;
+                description = text(&props, "desc").or_else(|| text(&props, "name"));
+            }
+            self.expect(";")?;
+            let value = u16::try_from(next)
+                .map_err(|_| error(self.line(), format!("{} = {} too wide", entry, next)))?;
+            values.push(EnumValue {
+                name: entry,
+                description,
+                value,
+            });
+            next += 1;
+        }
+        self.expect(";")?;
+        self.enums.insert(name, values);
+        Ok(())
+    }
+}
+
+/// Properties a nested component starts with: the defaults set in the parent
+/// or further out.
+fn inherited(parent: &Component, kind: Kind) -> HashMap<String, Value> {
+    let names: &[&str] = match kind {
+        Kind::Reg => &["regwidth", "sw"],
+        Kind::Field => &["fieldwidth", "sw"],
+        _ => &["regwidth", "fieldwidth", "sw"],
+    };
+    names
+        .iter()
+        .filter_map(|name| Some((name.to_string(), parent.defaults.get(*name)?.clone())))
+        .collect()
+}
+
+fn text(component: &Component, prop: &str) -> Option<String> {
+    match component.props.get(prop)? {
+        Value::Str(s) => Some(s.split_whitespace().collect::<Vec<_>>().join(" ")),
+        _ => None,
+    }
+}
+
+fn number(component: &Component, prop: &str) -> Option<u64> {
+    match component.props.get(prop)? {
+        Value::Number(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn ident<'a>(component: &'a Component, prop: &str) -> Option<&'a str> {
+    match component.props.get(prop)? {
+        Value::Ident(name) => Some(name),
+        _ => None,
+    }
+}
+
+/// Width of a register in bits, 32 unless set.
+fn regwidth(reg: &Component) -> u64 {
+    number(reg, "regwidth").unwrap_or(32)
+}
+
+fn description(component: &Component) -> Option<String> {
+    text(component, "desc").or_else(|| text(component, "name"))
+}
+
+/// Parses a SystemRDL file into the device model.
+pub fn parse(src: &str) -> Result<Device, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+        types: HashMap::new(),
+        enums: HashMap::new(),
+        instantiated: vec![],
+    };
+    let mut root = Component {
+        kind: Kind::Addrmap,
+        props: HashMap::new(),
+        defaults: HashMap::new(),
+        instances: vec![],
+    };
+    parser.body(&mut root)?;
+    if parser.pos < parser.tokens.len() {
+        return Err(parser.unexpected("a definition"));
+    }
+
+    // The top addrmap is the last one defined and not instantiated elsewhere,
+    // unless the file instantiates addrmaps at its root.
+    let (name, top) = if root
+        .instances
+        .iter()
+        .any(|inst| inst.component.kind == Kind::Addrmap)
+    {
+        ("device".to_string(), root)
+    } else {
+        let mut tops: Vec<(&String, &Component)> = parser
+            .types
+            .iter()
+            .filter(|(name, comp)| {
+                comp.kind == Kind::Addrmap && !parser.instantiated.contains(*name)
+            })
+            .collect();
+        tops.sort_by_key(|(name, _)| parser.type_position(name));
+        let (name, top) = tops
+            .pop()
+            .ok_or_else(|| ParseError::from("no addrmap defined"))?;
+        (name.clone(), top.clone())
+    };
+
+    let mut peripherals = vec![];
+    let mut own = Peripheral {
+        name: name.clone(),
+        description: description(&top),
+        base_address: 0,
+        registers: vec![],
+        clusters: vec![],
+    };
+    let mut next = 0;
+    for inst in top.instances.iter() {
+        for (suffix, address) in placements(inst, &mut next) {
+            if inst.component.kind == Kind::Addrmap {
+                let mut registers = vec![];
+                collect_registers(
+                    &parser.enums,
+                    &inst.component,
+                    address as u32,
+                    "",
+                    &mut registers,
+                )?;
+                peripherals.push(Peripheral {
+                    name: format!("{}{}", inst.name, suffix),
+                    description: description(&inst.component),
+                    base_address: address as u32,
+                    registers,
+                    clusters: vec![],
+                });
+            } else {
+                collect_instance(
+                    &parser.enums,
+                    inst,
+                    &suffix,
+                    address as u32,
+                    "",
+                    &mut own.registers,
+                )?;
+            }
+        }
+    }
+    if !own.registers.is_empty() {
+        peripherals.insert(0, own);
+    }
+    Ok(Device { name, peripherals })
+}
+
+impl Parser {
+    /// Position of the definition of the type `name`, for picking the last one.
+    fn type_position(&self, name: &str) -> usize {
+        self.tokens
+            .iter()
+            .rposition(|(token, _)| *token == Token::Ident(name.to_string()))
+            .unwrap_or(0)
+    }
+}
+
+fn instance_mut<'a>(component: &'a mut Component, name: &str) -> Option<&'a mut Instance> {
+    component
+        .instances
+        .iter_mut()
+        .find(|inst| inst.name == name)
+}
+
+/// Size of a component in bytes.
+fn size(component: &Component) -> u64 {
+    match component.kind {
+        Kind::Reg => regwidth(component) / 8,
+        Kind::Field => 0,
+        Kind::Regfile | Kind::Addrmap => {
+            let mut next = 0;
+            let mut end = 0;
+            for inst in component.instances.iter() {
+                for (_, address) in placements(inst, &mut next) {
+                    end = end.max(address + size(&inst.component));
+                }
+            }
+            end
+        }
+    }
+}
+
+/// Name suffixes and addresses of the elements of an instance, relative to its
+/// parent, with `next` the first free address.
+fn placements(inst: &Instance, next: &mut u64) -> Vec<(String, u64)> {
+    if inst.component.kind == Kind::Field {
+        return vec![];
+    }
+    let size = size(&inst.component);
+    let base = inst.address.unwrap_or_else(|| {
+        let align = size.max(1).next_power_of_two();
+        next.div_ceil(align) * align
+    });
+    let stride = inst.stride.unwrap_or(size);
+    let count: u64 = inst.dims.iter().product();
+    let placements: Vec<(String, u64)> = if inst.dims.is_empty() {
+        vec![(String::new(), base)]
+    } else {
+        (0..count)
+            .map(|i| (i.to_string(), base + i * stride))
+            .collect()
+    };
+    if let Some((_, last)) = placements.last() {
+        *next = last + size;
+    }
+    placements
+}
+
+fn collect_registers(
+    enums: &HashMap<String, Vec<EnumValue>>,
+    component: &Component,
+    address: u32,
+    prefix: &str,
+    registers: &mut Vec<Register>,
+) -> Result<(), ParseError> {
+    let mut next = 0;
+    for inst in component.instances.iter() {
+        for (suffix, offset) in placements(inst, &mut next) {
+            collect_instance(
+                enums,
+                inst,
+                &suffix,
+                address + offset as u32,
+                prefix,
+                registers,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds the register at `address`, or all registers of the register file or
+/// nested address map there.
+fn collect_instance(
+    enums: &HashMap<String, Vec<EnumValue>>,
+    inst: &Instance,
+    suffix: &str,
+    address: u32,
+    prefix: &str,
+    registers: &mut Vec<Register>,
+) -> Result<(), ParseError> {
+    let name = format!("{}{}{}", prefix, inst.name, suffix);
+    match inst.component.kind {
+        Kind::Reg => registers.push(register(enums, &inst.component, name, address)?),
+        Kind::Regfile | Kind::Addrmap => collect_registers(
+            enums,
+            &inst.component,
+            address,
+            format!("{}_", name).as_str(),
+            registers,
+        )?,
+        Kind::Field => (),
+    }
+    Ok(())
+}
+
+fn register(
+    enums: &HashMap<String, Vec<EnumValue>>,
+    reg: &Component,
+    name: String,
+    address: u32,
+) -> Result<Register, ParseError> {
+    let width = regwidth(reg);
+    if width > REG_BITS as u64 {
+        return Err(format!(
+            "register {}: regwidth {} wider than {}, set regwidth = {}",
+            name, width, REG_BITS, REG_BITS
+        )
+        .into());
+    }
+    let mut fields = vec![];
+    let mut reset_value = 0;
+    let mut read_side_effects = false;
+    let mut next_bit = 0;
+    for inst in reg
+        .instances
+        .iter()
+        .filter(|inst| inst.component.kind == Kind::Field)
+    {
+        let field = &inst.component;
+        let (offset, width) = match (inst.range, inst.dims.first()) {
+            (Some((msb, lsb)), _) => (msb.min(lsb), msb.abs_diff(lsb) + 1),
+            (None, Some(width)) => (next_bit, *width),
+            (None, None) => (next_bit, number(field, "fieldwidth").unwrap_or(1)),
+        };
+        next_bit = offset + width;
+        if width == 0 || next_bit > REG_BITS as u64 {
+            return Err(format!(
+                "field {}.{}: bits {}..{} beyond the register width",
+                name, inst.name, offset, next_bit
+            )
+            .into());
+        }
+        let max = u16::MAX >> (REG_BITS as u64 - width);
+        let fits = |what: &str, value: u64| match u16::try_from(value) {
+            Ok(value) if value <= max => Ok(value),
+            _ => Err(ParseError::from(format!(
+                "field {}.{}: {} 0x{:X} too wide",
+                name, inst.name, what, value
+            ))),
+        };
+        let reset = inst.reset.or_else(|| number(field, "reset")).unwrap_or(0);
+        reset_value |= fits("reset", reset)? << offset;
+        let enum_values = ident(field, "encode")
+            .and_then(|name| enums.get(name))
+            .cloned()
+            .unwrap_or_default();
+        for value in enum_values.iter() {
+            fits(format!("value {}", value.name).as_str(), value.value as u64)?;
+        }
+        read_side_effects |= matches!(ident(field, "onread"), Some("rclr" | "rset"))
+            || ident(field, "rclr") == Some("true")
+            || ident(field, "rset") == Some("true");
+        fields.push(Field {
+            name: inst.name.clone(),
+            description: description(field),
+            offset: offset as u8,
+            width: width as u8,
+            access: match ident(field, "sw") {
+                Some("r") => Access::ReadOnly,
+                Some("w" | "w1") => Access::WriteOnly,
+                _ => Access::ReadWrite,
+            },
+            write_constraint: None,
+            enum_values,
+        });
+    }
+    Ok(Register {
+        name,
+        description: description(reg),
+        address,
+        reset_value,
+        read_side_effects,
+        fields,
+        cluster: None,
+    })
+}
@@ -1,10 +1,14 @@
-use std::num::ParseIntError;
-
-use iced::widget::{button, center, column, row, text, text_input, Button};
-use iced::{color, Element, Renderer, Task, Theme};
+use iced::widget::{
+    button, center, checkbox, column, container, row, text, text_input, tooltip, Button,
+};
+use iced::{color, Color, Element, Renderer, Task, Theme};
 
+use crate::backend;
+use crate::expr::{self, Scope};
 use crate::field;
 use crate::field::Field;
+use crate::format::InputError;
+use crate::model;
 
 #[derive(Debug, Clone)]
 pub enum ValState {
@@ -13,22 +17,38 @@ pub enum ValState {
     Editing,
 }
 
+#[derive(Debug, Clone)]
+pub enum IoState {
+    Idle,
+    Pending,
+    Ok,
+    Error(backend::Error),
+}
+
 pub struct Reg16 {
-    pub name: String,
-    pub description: Option<String>,
+    pub model: model::Register,
+    /// Name shown in the tree, without the prefix of its cluster.
+    pub label: String,
+    /// Offset of the register from the base address of its peripheral.
+    pub offset: u32,
+    pub io: IoState,
     pub expanded: bool,
     pub state: ValState,
     pub value_read: u16,
     pub value_write: u16,
     pub input_text: String,
+    /// Editing state of the fields of the model, in the same order.
     pub fields: Vec<Field>,
     pub input_id: text_input::Id,
+    /// Marked for exporting the write value as code.
+    pub export: bool,
 }
 
 pub enum Action {
     None,
     Read,
     Write,
+    FormatChanged,
     Run(Task<Message>),
 }
 
@@ -41,16 +61,31 @@ pub enum Message {
     InputChanged(String),
     WriteValueSubmit,
     FieldChanged(usize, field::Message),
-}
-
-#[derive(Debug, Clone)]
-pub struct EnumValue {
-    pub name: String,
-    pub description: Option<String>,
-    pub value: u16,
+    ReadDone(Result<u16, backend::Error>),
+    WriteDone(Result<(), backend::Error>),
+    /// The value was written to the register from outside the tree.
+    Written(u16),
+    ExportToggled(bool),
 }
 
 impl Reg16 {
+    pub fn new(model: model::Register, fields: Vec<Field>) -> Self {
+        Reg16 {
+            label: model.name.clone(),
+            offset: 0,
+            model,
+            io: IoState::Idle,
+            expanded: false,
+            state: ValState::None,
+            value_read: 0,
+            value_write: 0,
+            input_text: String::from("0x0000"),
+            fields,
+            input_id: text_input::Id::unique(),
+            export: false,
+        }
+    }
+
     pub fn update(&mut self, message: Message) -> Action {
         match message {
             Message::ToggleExpand => {
@@ -67,22 +102,17 @@ impl Reg16 {
             }
             Message::InputChanged(val) => {
                 self.input_text = val;
-                if let Ok(value) = from_str_to_u16(self.input_text.as_str()) {
-                    self.value_write = value;
+                if let Ok(value) = self.parse_input() {
+                    self.set_value_write(value);
                     self.state = ValState::Selected;
-                    for field in self.fields.iter_mut() {
-                        field.set_value_write_from_reg(value);
-                    }
                 }
                 Action::None
             }
             Message::WriteValueSubmit => {
-                if let Ok(value) = from_str_to_u16(self.input_text.as_str()) {
-                    self.value_write = value;
+                if let Ok(value) = self.parse_input() {
+                    self.set_value_write(value);
                     self.state = ValState::Selected;
-                    for field in self.fields.iter_mut() {
-                        field.set_value_write_from_reg(value);
-                    }
+                    self.io = IoState::Pending;
                     Action::Write
                 } else {
                     Action::None
@@ -92,7 +122,7 @@ impl Reg16 {
                 field::Message::Select(id) => {
                     for (j, field) in self.fields.iter_mut().enumerate() {
                         if j == index {
-                            field.update(field::Message::Select(id.clone()));
+                            field.update(&self.model.fields[j], field::Message::Select(id.clone()));
                         } else {
                             field.state = ValState::None;
                         }
@@ -100,45 +130,125 @@ impl Reg16 {
                     Action::None
                 }
                 field::Message::InputChanged(_) => {
-                    self.fields[index].update(message);
+                    self.fields[index].update(&self.model.fields[index], message);
                     Action::None
                 }
-                field::Message::WriteValueSubmit => {
-                    if let Ok(value) = from_str_to_u16(self.fields[index].input_text.as_str()) {
-                        self.value_write =
-                            self.fields[index].value_reg_from_field(self.value_write, value);
-                        self.input_text = from_u16_to_hex(self.value_write);
-                        self.fields[index].state = ValState::None;
-                        for field in self.fields.iter_mut() {
-                            field.set_value_write_from_reg(self.value_write)
-                        }
-                        Action::Run(text_input::focus(self.input_id.clone()))
-                    } else {
-                        Action::None
-                    }
+                field::Message::ScaleFactorChanged(_) | field::Message::ScaleUnitChanged(_) => {
+                    // Saved on submit rather than on every keystroke
+                    self.fields[index].update(&self.model.fields[index], message);
+                    Action::None
                 }
-                _ => {
-                    self.fields[index].update(message);
+                field::Message::FormatSelected(_) | field::Message::ScaleSubmit => {
+                    self.fields[index].update(&self.model.fields[index], message);
+                    Action::FormatChanged
+                }
+                field::Message::WriteValueSubmit => self.submit_field(index),
+                field::Message::FlagToggled(..) => {
+                    // Keep editing so several flags can be toggled in a row
+                    self.fields[index].update(&self.model.fields[index], message);
+                    let _ = self.apply_field(index);
                     Action::None
                 }
+                field::Message::ValSelected(_) => {
+                    self.fields[index].update(&self.model.fields[index], message);
+                    self.submit_field(index)
+                }
             },
-            Message::Read => Action::Read,
+            // Sent by the keyboard shortcuts too, which the disabled buttons don't stop
+            Message::Read | Message::Write if self.is_pending() => Action::None,
+            Message::Read => {
+                self.io = IoState::Pending;
+                Action::Read
+            }
             Message::Write => {
-                if let Ok(value) = from_str_to_u16(self.input_text.as_str()) {
-                    self.value_write = value;
+                if let Ok(value) = self.parse_input() {
+                    self.set_value_write(value);
                     self.state = ValState::Selected;
-                    for field in self.fields.iter_mut() {
-                        field.set_value_write_from_reg(value);
-                    }
+                    self.io = IoState::Pending;
                     Action::Write
                 } else {
                     Action::None
                 }
             }
+            Message::ReadDone(Ok(value)) => {
+                self.value_read = value;
+                for (field, model) in self.fields.iter_mut().zip(&self.model.fields) {
+                    field.value_read = model.decode(value);
+                }
+                self.io = IoState::Ok;
+                Action::None
+            }
+            Message::WriteDone(Ok(())) => {
+                self.io = IoState::Ok;
+                Action::None
+            }
+            Message::ExportToggled(export) => {
+                self.export = export;
+                Action::None
+            }
+            Message::Written(value) => {
+                self.set_value_write(value);
+                self.input_text = from_u16_to_hex(value);
+                self.io = IoState::Ok;
+                Action::None
+            }
+            Message::ReadDone(Err(err)) | Message::WriteDone(Err(err)) => {
+                self.io = IoState::Error(err);
+                Action::None
+            }
+        }
+    }
+
+    /// Applies the value typed into the field input to the register write value.
+    fn apply_field(&mut self, index: usize) -> Result<(), InputError> {
+        let model = &self.model.fields[index];
+        let value = self.fields[index].parse_input(model)?;
+        self.set_value_write(model.encode(self.value_write, value));
+        self.input_text = from_u16_to_hex(self.value_write);
+        Ok(())
+    }
+
+    fn set_value_write(&mut self, value: u16) {
+        self.value_write = value;
+        for (field, model) in self.fields.iter_mut().zip(&self.model.fields) {
+            field.value_write = model.decode(value);
         }
     }
 
-    pub fn view(&self) -> Element<Message, Theme, Renderer> {
+    /// Applies the field input and ends editing the field.
+    fn submit_field(&mut self, index: usize) -> Action {
+        match self.apply_field(index) {
+            Ok(()) => {
+                self.fields[index].state = ValState::None;
+                self.fields[index].error = None;
+                Action::Run(text_input::focus(self.input_id.clone()))
+            }
+            Err(err) => {
+                self.fields[index].error = Some(err);
+                Action::None
+            }
+        }
+    }
+
+    /// Evaluates the register input, which may be an expression over field and
+    /// enumerated value names.
+    pub fn parse_input(&self) -> Result<u16, expr::Error> {
+        expr::eval(
+            self.input_text.as_str(),
+            Scope::Register(&self.model.fields),
+        )
+    }
+
+    /// Discards the text typed into the register input.
+    pub fn cancel_edit(&mut self) {
+        self.input_text = from_u16_to_hex(self.value_write);
+    }
+
+    fn is_pending(&self) -> bool {
+        matches!(self.io, IoState::Pending)
+    }
+
+    pub fn view(&self) -> Element<'_, Message, Theme, Renderer> {
         let but_text = if self.expanded { "-" } else { "+" };
         let read_value_str = format!("0x{:04X}", self.value_read);
         let read_value = text_button(text(read_value_str.clone())).on_press(Message::Select);
@@ -153,31 +263,58 @@ impl Reg16 {
                         .on_input(Message::InputChanged)
                         .on_submit(Message::WriteValueSubmit),
                 )
+                .push_maybe(self.input_preview())
                 .spacing(5);
         }
         let mut reg = row![
             text_button(but_text).on_press(Message::ToggleExpand),
-            text_button(self.name.as_str()),
+            described(
+                checkbox("", self.export).on_toggle(Message::ExportToggled),
+                Some("Include in the code export")
+            ),
+            text(format!(
+                "0x{:08X} +0x{:04X}",
+                self.model.address, self.offset
+            )),
+            described(
+                text_button(self.label.as_str()).style(match self.state {
+                    ValState::None => button::text,
+                    ValState::Selected | ValState::Editing => selected,
+                }),
+                self.model.description.as_deref()
+            ),
             values_column,
+            self.io_status(),
         ]
         .spacing(10);
         if self.expanded {
-            let field_names_col =
-                column(self.fields.iter().map(|field| field.name.as_str().into()));
-            let fields_col = column(self.fields.iter().map(Field::view).enumerate().map(
-                |(index, field)| field.map(move |message| Message::FieldChanged(index, message)),
-            ));
+            let field_names_col = column(
+                self.model
+                    .fields
+                    .iter()
+                    .map(|field| described(field.name.as_str(), field.description.as_deref())),
+            );
+            let fields_col = column(
+                self.fields
+                    .iter()
+                    .zip(&self.model.fields)
+                    .map(|(field, model)| field.view(model))
+                    .enumerate()
+                    .map(|(index, field)| {
+                        field.map(move |message| Message::FieldChanged(index, message))
+                    }),
+            );
             let button_col = column![
                 button(center(text("R")))
                     .height(25)
                     .width(25)
                     .padding(0)
-                    .on_press(Message::Read),
+                    .on_press_maybe((!self.is_pending()).then_some(Message::Read)),
                 button(center(text("W")))
                     .height(25)
                     .width(25)
                     .padding(0)
-                    .on_press(Message::Write)
+                    .on_press_maybe((!self.is_pending()).then_some(Message::Write))
             ]
             .spacing(5);
             reg = reg.push(button_col);
@@ -187,6 +324,27 @@ impl Reg16 {
         reg.into()
     }
 
+    fn input_preview(&self) -> Option<Element<'_, Message, Theme, Renderer>> {
+        if expr::is_literal(self.input_text.as_str()) {
+            return None;
+        }
+        Some(match self.parse_input() {
+            Ok(value) => text(format!("= {}", from_u16_to_hex(value))).into(),
+            Err(err) => text(err.to_string()).color(color!(0xDD3333)).into(),
+        })
+    }
+
+    fn io_status(&self) -> Element<'_, Message, Theme, Renderer> {
+        match &self.io {
+            IoState::Idle => text("").into(),
+            IoState::Pending => text("...").into(),
+            IoState::Ok => text("ok").color(color!(0x33AA33)).into(),
+            IoState::Error(err) => text(format!("error: {}", err))
+                .color(color!(0xDD3333))
+                .into(),
+        }
+    }
+
     fn _value_button<'a>(&self) -> Element<'a, Message, Theme, Renderer> {
         let value = format!("0x{:04X}", self.value_read);
         let val_but = button(text(value.clone()))
@@ -219,15 +377,30 @@ fn text_button<'a>(
     button(content).style(button::text).padding(0)
 }
 
-fn from_str_to_u16(src: &str) -> Result<u16, ParseIntError> {
-    let mut src = src.trim();
-    src = src.trim_start_matches("+");
-    if let Some(src) = src.strip_prefix("0x") {
-        u16::from_str_radix(src, 16)
-    } else if let Some(src) = src.strip_prefix("0b") {
-        u16::from_str_radix(src, 2)
-    } else {
-        src.parse::<u16>()
+/// Style of the item under the cursor.
+pub fn selected(theme: &Theme, status: button::Status) -> button::Style {
+    button::Style {
+        text_color: Color::WHITE,
+        ..button::text(theme, status).with_background(color!(0x3399FF))
+    }
+}
+
+/// Shows `description` in a tooltip when hovering `content`, if there is one.
+pub fn described<'a, Message: 'a>(
+    content: impl Into<Element<'a, Message, Theme, Renderer>>,
+    description: Option<&'a str>,
+) -> Element<'a, Message, Theme, Renderer> {
+    match description {
+        Some(description) => tooltip(
+            content,
+            container(text(description).size(12))
+                .padding(5)
+                .max_width(400)
+                .style(container::rounded_box),
+            tooltip::Position::Bottom,
+        )
+        .into(),
+        None => content.into(),
     }
 }
 
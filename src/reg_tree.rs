@@ -0,0 +1,480 @@
+//! The register tree as a component to embed in iced applications.
+//!
+//! [`RegTree`] owns the peripherals and handles selection, editing and keyboard
+//! navigation. It doesn't access the device itself: reads and writes are handed
+//! to the embedding application as [`Action`]s, which answers them with
+//! [`Message::ReadDone`] and [`Message::WriteDone`].
+
+use std::collections::HashMap;
+
+use iced::widget::scrollable::{self, RelativeOffset, Scrollable};
+use iced::widget::{button, column, row, text, text_input};
+use iced::{color, Element, Renderer, Subscription, Task, Theme};
+
+use crate::backend::{self, REG_BYTES};
+use crate::bulk_read::Target;
+use crate::field::{self, Field};
+use crate::format::Format;
+use crate::model;
+use crate::nav::{self, Cursor};
+use crate::peripheral::{self, Peripheral, Sort};
+use crate::reg16::{self, Reg16, ValState};
+
+pub struct RegTree {
+    pub peripherals: Vec<Peripheral>,
+    cursor: Option<Cursor>,
+    pub sort: Sort,
+    goto_input: String,
+    /// Why the last "go to address" found nothing.
+    goto_error: Option<String>,
+    scroll_id: scrollable::Id,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Peripheral(usize, peripheral::Message),
+    Nav(nav::Command),
+    ToggleSort,
+    GotoChanged(String),
+    /// Select the register covering the address typed in the go to box.
+    Goto,
+    ReadDone(Target, Result<u16, backend::Error>),
+    WriteDone(Target, Result<(), backend::Error>),
+    /// The application wrote the value to the register on its own, e.g. from a
+    /// script.
+    Written(Target, u16),
+}
+
+pub enum Action {
+    None,
+    /// Read the register and answer with [`Message::ReadDone`].
+    Read(Target),
+    /// Write the value to the register and answer with [`Message::WriteDone`].
+    Write(Target, u16),
+    /// Read every register of the given peripherals.
+    ReadAll(Vec<usize>),
+    /// A field display format changed, the application may persist it.
+    FormatChanged,
+    Run(Task<Message>),
+}
+
+impl RegTree {
+    pub fn new(peripherals: Vec<Peripheral>) -> Self {
+        Self {
+            peripherals,
+            cursor: None,
+            sort: Sort::default(),
+            goto_input: String::new(),
+            goto_error: None,
+            scroll_id: scrollable::Id::unique(),
+        }
+    }
+
+    /// Builds the tree of a device, displaying fields in the `formats` keyed by
+    /// `PERIPH.REG.FIELD` path.
+    pub fn from_device(device: model::Device, formats: &HashMap<String, Format>) -> Self {
+        let peripherals = device
+            .peripherals
+            .into_iter()
+            .map(|periph| {
+                let labels: Vec<String> = periph
+                    .registers
+                    .iter()
+                    .map(|reg| periph.short_name(&reg.name, reg.cluster).to_string())
+                    .collect();
+                let clusters = periph
+                    .clusters
+                    .iter()
+                    .map(|cluster| peripheral::Cluster {
+                        model: cluster.clone(),
+                        label: periph.short_name(&cluster.name, cluster.parent).to_string(),
+                        expanded: true,
+                        selected: false,
+                    })
+                    .collect();
+                let regs = periph
+                    .registers
+                    .into_iter()
+                    .zip(labels)
+                    .map(|(reg, label)| {
+                        let offset = reg.address.wrapping_sub(periph.base_address);
+                        let fields = reg
+                            .fields
+                            .iter()
+                            .map(|field| {
+                                let format = formats
+                                    .get(&format!("{}.{}.{}", periph.name, reg.name, field.name))
+                                    .cloned()
+                                    .unwrap_or(Format::default_for_width(field.width));
+                                Field::new(field, format)
+                            })
+                            .collect();
+                        let mut reg = Reg16::new(reg, fields);
+                        reg.label = label;
+                        reg.offset = offset;
+                        reg
+                    })
+                    .collect();
+                Peripheral {
+                    name: periph.name,
+                    description: periph.description,
+                    base_address: periph.base_address,
+                    expanded: true,
+                    selected: false,
+                    regs,
+                    clusters,
+                }
+            })
+            .collect();
+        Self::new(peripherals)
+    }
+
+    /// Registers of the tree with the index of their peripheral and register.
+    pub fn targets(&self) -> impl Iterator<Item = Target> + '_ {
+        self.peripherals.iter().enumerate().flat_map(|(p, periph)| {
+            periph.regs.iter().enumerate().map(move |(r, reg)| Target {
+                periph: p,
+                reg: r,
+                address: reg.model.address,
+            })
+        })
+    }
+
+    /// Registers marked for export with their peripheral name and write value.
+    pub fn marked(&self) -> impl Iterator<Item = (&str, &model::Register, u16)> {
+        self.peripherals.iter().flat_map(|periph| {
+            periph
+                .regs
+                .iter()
+                .filter(|reg| reg.export)
+                .map(move |reg| (periph.name.as_str(), &reg.model, reg.value_write))
+        })
+    }
+
+    /// Formats of all fields not using the default one, keyed by `PERIPH.REG.FIELD`
+    /// path.
+    pub fn formats(&self) -> impl Iterator<Item = (String, &Format)> {
+        self.peripherals.iter().flat_map(|periph| {
+            periph.regs.iter().flat_map(move |reg| {
+                reg.fields
+                    .iter()
+                    .zip(&reg.model.fields)
+                    .filter(|(field, model)| field.format != Format::default_for_width(model.width))
+                    .map(move |(field, model)| {
+                        (
+                            format!("{}.{}.{}", periph.name, reg.model.name, model.name),
+                            &field.format,
+                        )
+                    })
+            })
+        })
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::Nav(command) => self.navigate(command),
+            Message::ToggleSort => {
+                self.sort = match self.sort {
+                    Sort::Address => Sort::Name,
+                    Sort::Name => Sort::Address,
+                };
+                Action::None
+            }
+            Message::GotoChanged(input) => {
+                self.goto_input = input;
+                self.goto_error = None;
+                Action::None
+            }
+            Message::Goto => self.goto(),
+            Message::ReadDone(target, result) => {
+                let _ = self.peripherals[target.periph].regs[target.reg]
+                    .update(reg16::Message::ReadDone(result));
+                Action::None
+            }
+            Message::WriteDone(target, result) => {
+                let _ = self.peripherals[target.periph].regs[target.reg]
+                    .update(reg16::Message::WriteDone(result));
+                Action::None
+            }
+            Message::Written(target, value) => {
+                let _ = self.peripherals[target.periph].regs[target.reg]
+                    .update(reg16::Message::Written(value));
+                Action::None
+            }
+            Message::Peripheral(p, peripheral::Message::Reg(idx, reg16::Message::Select)) => {
+                self.select(Cursor::Reg(p, idx));
+                Action::Run(text_input::focus(
+                    self.peripherals[p].regs[idx].input_id.clone(),
+                ))
+            }
+            Message::Peripheral(
+                p,
+                peripheral::Message::Reg(
+                    idx,
+                    reg16::Message::FieldChanged(field_idx, field::Message::Select(id)),
+                ),
+            ) => {
+                // A second click on a selected field starts editing it
+                let reselected = !matches!(
+                    self.peripherals[p].regs[idx].fields[field_idx].state,
+                    ValState::None
+                );
+                self.select(Cursor::Field(p, idx, field_idx));
+                if reselected {
+                    let _ = self.peripherals[p].regs[idx].update(reg16::Message::FieldChanged(
+                        field_idx,
+                        field::Message::Select(id.clone()),
+                    ));
+                }
+                Action::Run(text_input::focus(id.clone()))
+            }
+            Message::Peripheral(p, msg) => match self.peripherals[p].update(msg) {
+                peripheral::Action::None => Action::None,
+                peripheral::Action::ReadAll => Action::ReadAll(vec![p]),
+                peripheral::Action::Reg(idx, action) => self.reg_action(p, idx, action),
+            },
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        nav::subscription().map(Message::Nav)
+    }
+
+    /// Moves the keyboard cursor, leaving only the item under it selected.
+    fn select(&mut self, cursor: Cursor) {
+        for periph in self.peripherals.iter_mut() {
+            periph.selected = false;
+            for cluster in periph.clusters.iter_mut() {
+                cluster.selected = false;
+            }
+            for reg in periph.regs.iter_mut() {
+                reg.state = ValState::None;
+                for field in reg.fields.iter_mut() {
+                    field.state = ValState::None;
+                }
+            }
+        }
+        match cursor {
+            Cursor::Peripheral(p) => self.peripherals[p].selected = true,
+            Cursor::Cluster(p, c) => self.peripherals[p].clusters[c].selected = true,
+            Cursor::Reg(p, r) => self.peripherals[p].regs[r].state = ValState::Selected,
+            Cursor::Field(p, r, f) => {
+                self.peripherals[p].regs[r].fields[f].state = ValState::Selected
+            }
+        }
+        self.cursor = Some(cursor);
+    }
+
+    fn navigate(&mut self, command: nav::Command) -> Action {
+        match (command, self.cursor) {
+            (nav::Command::Up | nav::Command::Down, cursor) => {
+                let delta = if command == nav::Command::Up { -1 } else { 1 };
+                if let Some(cursor) = nav::step(&self.peripherals, self.sort, cursor, delta) {
+                    self.select(cursor);
+                }
+                return Action::Run(unfocus());
+            }
+            (_, None) => (),
+            (nav::Command::Collapse, Some(cursor)) => match cursor {
+                Cursor::Peripheral(p) => self.peripherals[p].expanded = false,
+                Cursor::Cluster(p, c) if self.peripherals[p].clusters[c].expanded => {
+                    self.peripherals[p].clusters[c].expanded = false
+                }
+                Cursor::Reg(p, r) if self.peripherals[p].regs[r].expanded => {
+                    self.peripherals[p].regs[r].expanded = false
+                }
+                _ => {
+                    if let Some(parent) = cursor.parent(&self.peripherals) {
+                        self.select(parent);
+                        return Action::Run(unfocus());
+                    }
+                }
+            },
+            (nav::Command::Expand, Some(cursor)) => match cursor {
+                Cursor::Peripheral(p) if !self.peripherals[p].expanded => {
+                    self.peripherals[p].expanded = true
+                }
+                Cursor::Cluster(p, c) if !self.peripherals[p].clusters[c].expanded => {
+                    self.peripherals[p].clusters[c].expanded = true
+                }
+                Cursor::Reg(p, r) if !self.peripherals[p].regs[r].expanded => {
+                    self.peripherals[p].regs[r].expanded = true
+                }
+                Cursor::Peripheral(_) | Cursor::Cluster(..) | Cursor::Reg(..) => {
+                    // Already expanded, step into the first child
+                    if let Some(child) = nav::step(&self.peripherals, self.sort, Some(cursor), 1)
+                        .filter(|child| child.parent(&self.peripherals) == Some(cursor))
+                    {
+                        self.select(child);
+                    }
+                }
+                Cursor::Field(..) => (),
+            },
+            (nav::Command::Edit, Some(cursor)) => match cursor {
+                Cursor::Peripheral(p) => {
+                    self.peripherals[p].expanded = !self.peripherals[p].expanded
+                }
+                Cursor::Cluster(p, c) => {
+                    let cluster = &mut self.peripherals[p].clusters[c];
+                    cluster.expanded = !cluster.expanded;
+                }
+                Cursor::Reg(p, r) => {
+                    let reg = &mut self.peripherals[p].regs[r];
+                    reg.expanded = true;
+                    return Action::Run(text_input::focus(reg.input_id.clone()));
+                }
+                Cursor::Field(p, r, f) => return self.edit_field(p, r, f),
+            },
+            (nav::Command::Cancel, Some(cursor)) => {
+                match cursor {
+                    Cursor::Peripheral(_) | Cursor::Cluster(..) => (),
+                    Cursor::Reg(p, r) => self.peripherals[p].regs[r].cancel_edit(),
+                    Cursor::Field(p, r, f) => {
+                        let reg = &mut self.peripherals[p].regs[r];
+                        reg.fields[f].cancel_edit(&reg.model.fields[f]);
+                    }
+                }
+                return Action::Run(unfocus());
+            }
+            (nav::Command::Read | nav::Command::Write, Some(cursor)) => {
+                if let Cursor::Reg(p, r) | Cursor::Field(p, r, _) = cursor {
+                    let message = if command == nav::Command::Read {
+                        reg16::Message::Read
+                    } else {
+                        reg16::Message::Write
+                    };
+                    return self
+                        .update(Message::Peripheral(p, peripheral::Message::Reg(r, message)));
+                }
+            }
+            (nav::Command::NextField | nav::Command::PreviousField, Some(cursor)) => {
+                if let Cursor::Reg(p, r) | Cursor::Field(p, r, _) = cursor {
+                    self.peripherals[p].regs[r].expanded = true;
+                }
+                let delta = if command == nav::Command::NextField {
+                    1
+                } else {
+                    -1
+                };
+                if let Some(Cursor::Field(p, r, f)) =
+                    nav::cycle_field(&self.peripherals, cursor, delta)
+                {
+                    self.select(Cursor::Field(p, r, f));
+                    return self.edit_field(p, r, f);
+                }
+            }
+        }
+        Action::None
+    }
+
+    /// Selects the register covering the typed address, expanding its
+    /// peripheral and clusters, and scrolls to it.
+    fn goto(&mut self) -> Action {
+        let Some(address) = model::parse_number(self.goto_input.trim()) else {
+            self.goto_error = Some(format!("invalid address '{}'", self.goto_input.trim()));
+            return Action::None;
+        };
+        let found = self.peripherals.iter().enumerate().find_map(|(p, periph)| {
+            periph
+                .regs
+                .iter()
+                .position(|reg| {
+                    let start = reg.model.address as u64;
+                    (start..start + REG_BYTES as u64).contains(&address)
+                })
+                .map(|r| (p, r))
+        });
+        let Some((p, r)) = found else {
+            self.goto_error = Some(format!("no register at 0x{:08X}", address));
+            return Action::None;
+        };
+
+        let periph = &mut self.peripherals[p];
+        periph.expanded = true;
+        let mut cluster = periph.regs[r].model.cluster;
+        while let Some(c) = cluster {
+            periph.clusters[c].expanded = true;
+            cluster = periph.clusters[c].model.parent;
+        }
+        self.select(Cursor::Reg(p, r));
+
+        // Rows differ in height, the position among them is close enough
+        let rows = nav::visible(&self.peripherals, self.sort);
+        let index = rows
+            .iter()
+            .position(|row| *row == Cursor::Reg(p, r))
+            .unwrap_or(0);
+        let y = index as f32 / rows.len().saturating_sub(1).max(1) as f32;
+        Action::Run(Task::batch([
+            scrollable::snap_to(self.scroll_id.clone(), RelativeOffset { x: 0.0, y }),
+            unfocus(),
+        ]))
+    }
+
+    fn edit_field(&mut self, p: usize, r: usize, f: usize) -> Action {
+        let field = &mut self.peripherals[p].regs[r].fields[f];
+        field.state = ValState::Editing;
+        Action::Run(text_input::focus(field.input_id.clone()))
+    }
+
+    fn reg_action(&mut self, p: usize, idx: usize, action: reg16::Action) -> Action {
+        let reg = &self.peripherals[p].regs[idx];
+        let target = Target {
+            periph: p,
+            reg: idx,
+            address: reg.model.address,
+        };
+        match action {
+            reg16::Action::None => Action::None,
+            reg16::Action::Read => Action::Read(target),
+            reg16::Action::Write => Action::Write(target, reg.value_write),
+            reg16::Action::FormatChanged => Action::FormatChanged,
+            reg16::Action::Run(task) => Action::Run(
+                task.map(move |msg| Message::Peripheral(p, peripheral::Message::Reg(idx, msg))),
+            ),
+        }
+    }
+
+    /// The tool bar over the scrollable tree.
+    pub fn view(&self) -> Element<'_, Message, Theme, Renderer> {
+        let toolbar = row![
+            button(text(match self.sort {
+                Sort::Address => "Sort by name",
+                Sort::Name => "Sort by address",
+            }))
+            .padding([0, 5])
+            .on_press(Message::ToggleSort),
+            text_input("Go to address", self.goto_input.as_str())
+                .width(160)
+                .on_input(Message::GotoChanged)
+                .on_submit(Message::Goto),
+        ]
+        .push_maybe(
+            self.goto_error
+                .as_deref()
+                .map(|err| text(err).color(color!(0xDD3333))),
+        )
+        .spacing(10);
+        let peripherals = column(
+            peripheral::order(&self.peripherals, self.sort)
+                .into_iter()
+                .map(|index| {
+                    self.peripherals[index]
+                        .view(self.sort)
+                        .map(move |message| Message::Peripheral(index, message))
+                }),
+        )
+        .spacing(20);
+        column![
+            toolbar,
+            Scrollable::new(peripherals).id(self.scroll_id.clone())
+        ]
+        .spacing(20)
+        .into()
+    }
+}
+
+/// Unfocuses every text input, as no widget has a fresh id.
+fn unfocus<T>() -> Task<T> {
+    text_input::focus(text_input::Id::unique())
+}
@@ -0,0 +1,439 @@
+//! A concise register description format for ad-hoc devices like I2C sensors
+//! and small FPGA blocks, written in YAML or TOML:
+//!
+//! ```yaml
+//! name: BME280
+//! peripherals:
+//!   SENSOR:
+//!     base_address: 0x76
+//!     registers:
+//!       CTRL_MEAS:
+//!         offset: 0xF4
+//!         reset: 0x00
+//!         fields:
+//!           MODE:
+//!             bits: "1:0"
+//!             enum: { Sleep: 0, Forced: 1, Normal: 3 }
+//!           OSRS_P: [4:2]
+//!           EN: 0
+//! ```
+//!
+//! A field is either just its bits, `[msb:lsb]` or a single bit number, or a
+//! table with the `bits` and optional `description`, `access` (`read-write`,
+//! `read-only` or `write-only`), `enum` values and `write` constraint, the
+//! `[min, max]` range, `enumerated` or `as-read`. Numbers may be written as
+//! strings, `"0x1F"` or Verilog style `"8'h1F"`. Peripherals, registers, fields
+//! and enumerated values are ordered by address, offset and value. TOML has no
+//! bare `msb:lsb`, ranges are written `["4:2"]` or `"4:2"` there.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::model::{
+    parse_number, Access, Device, EnumValue, Field, ParseError, Peripheral, Register,
+    WriteConstraint, REG_BITS,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeviceDesc {
+    name: String,
+    #[serde(default)]
+    peripherals: BTreeMap<String, PeripheralDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PeripheralDesc {
+    description: Option<String>,
+    base_address: Number,
+    #[serde(default)]
+    registers: BTreeMap<String, RegisterDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegisterDesc {
+    offset: Number,
+    description: Option<String>,
+    reset: Option<Number>,
+    /// Access of the fields without their own.
+    access: Option<AccessDesc>,
+    #[serde(default)]
+    read_side_effects: bool,
+    #[serde(default)]
+    fields: BTreeMap<String, FieldDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum FieldDesc {
+    Bits(Bits),
+    Full {
+        bits: Bits,
+        description: Option<String>,
+        access: Option<AccessDesc>,
+        #[serde(default, rename = "enum")]
+        enum_values: BTreeMap<String, EnumDesc>,
+        write: Option<WriteDesc>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum EnumDesc {
+    Value(Number),
+    Full {
+        value: Number,
+        description: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+enum AccessDesc {
+    #[serde(rename = "read-write", alias = "rw")]
+    ReadWrite,
+    #[serde(rename = "read-only", alias = "ro")]
+    ReadOnly,
+    #[serde(rename = "write-only", alias = "wo")]
+    WriteOnly,
+}
+
+impl From<AccessDesc> for Access {
+    fn from(access: AccessDesc) -> Self {
+        match access {
+            AccessDesc::ReadWrite => Access::ReadWrite,
+            AccessDesc::ReadOnly => Access::ReadOnly,
+            AccessDesc::WriteOnly => Access::WriteOnly,
+        }
+    }
+}
+
+/// Values that may be written to a field.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WriteDesc {
+    Range([Number; 2]),
+    Kind(WriteKind),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+enum WriteKind {
+    #[serde(rename = "enumerated")]
+    Enumerated,
+    #[serde(rename = "as-read")]
+    AsRead,
+}
+
+impl WriteDesc {
+    fn constraint(&self, field: &Field) -> Result<WriteConstraint, ParseError> {
+        match self {
+            WriteDesc::Range([min, max]) => {
+                let min = min.value(format!("write min of {}", field.name).as_str())?;
+                let max = max.value(format!("write max of {}", field.name).as_str())?;
+                if min > max || max > field.max_value() as u64 {
+                    return Err(format!(
+                        "field {}: invalid write range [{}, {}]",
+                        field.name, min, max
+                    )
+                    .into());
+                }
+                Ok(WriteConstraint::Range {
+                    min: min as u16,
+                    max: max as u16,
+                })
+            }
+            WriteDesc::Kind(WriteKind::Enumerated) => Ok(WriteConstraint::EnumeratedValues),
+            WriteDesc::Kind(WriteKind::AsRead) => Ok(WriteConstraint::WriteAsRead),
+        }
+    }
+}
+
+/// A number written as such or as a string in any notation of
+/// [`parse_number`], which also holds bit ranges.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Number {
+    Int(u64),
+    Text(String),
+}
+
+/// Bits of a field, `[msb:lsb]` being a sequence of the `msb:lsb` string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Bits {
+    Range([Number; 1]),
+    Number(Number),
+}
+
+impl Bits {
+    fn bits(&self, field: &str) -> Result<(u8, u8), ParseError> {
+        match self {
+            Bits::Range([number]) | Bits::Number(number) => number.bits(field),
+        }
+    }
+}
+
+impl Number {
+    fn value(&self, what: &str) -> Result<u64, ParseError> {
+        match self {
+            Number::Int(value) => Ok(*value),
+            Number::Text(text) => parse_number(text.trim())
+                .ok_or_else(|| format!("invalid {} '{}'", what, text).into()),
+        }
+    }
+
+    /// Offset and width of the `msb:lsb` range or the single bit.
+    fn bits(&self, field: &str) -> Result<(u8, u8), ParseError> {
+        let (msb, lsb) = match self {
+            Number::Text(text) if text.contains(':') => {
+                let (msb, lsb) = text.split_once(':').unwrap_or_default();
+                let bit = |text: &str| {
+                    parse_number(text.trim()).ok_or_else(|| {
+                        ParseError::from(format!("field {}: invalid bits '{}'", field, text))
+                    })
+                };
+                (bit(msb)?, bit(lsb)?)
+            }
+            _ => {
+                let bit = self.value(format!("bits of field {}", field).as_str())?;
+                (bit, bit)
+            }
+        };
+        let (msb, lsb) = (msb.max(lsb), msb.min(lsb));
+        if msb >= REG_BITS as u64 {
+            return Err(format!("field {}: bit {} beyond the register width", field, msb).into());
+        }
+        Ok((lsb as u8, (msb - lsb + 1) as u8))
+    }
+}
+
+pub fn parse_yaml(src: &str) -> Result<Device, ParseError> {
+    build(serde_yaml::from_str(src)?)
+}
+
+pub fn parse_toml(src: &str) -> Result<Device, ParseError> {
+    build(toml::from_str(src)?)
+}
+
+fn build(desc: DeviceDesc) -> Result<Device, ParseError> {
+    let mut peripherals = vec![];
+    for (name, periph) in desc.peripherals {
+        let base_address = periph.base_address.value("base_address")? as u32;
+        let mut registers = vec![];
+        for (name, reg) in periph.registers {
+            let mut fields = vec![];
+            for (name, field) in reg.fields {
+                fields.push(match field {
+                    FieldDesc::Bits(bits) => {
+                        let (offset, width) = bits.bits(&name)?;
+                        Field {
+                            name,
+                            description: None,
+                            offset,
+                            width,
+                            access: reg.access.map(Access::from).unwrap_or_default(),
+                            write_constraint: None,
+                            enum_values: vec![],
+                        }
+                    }
+                    FieldDesc::Full {
+                        bits,
+                        description,
+                        access,
+                        enum_values,
+                        write,
+                    } => {
+                        let (offset, width) = bits.bits(&name)?;
+                        let mut field = Field {
+                            name,
+                            description,
+                            offset,
+                            width,
+                            access: access.or(reg.access).map(Access::from).unwrap_or_default(),
+                            write_constraint: None,
+                            enum_values: vec![],
+                        };
+                        for (name, value) in enum_values {
+                            let (value, description) = match value {
+                                EnumDesc::Value(value) => (value, None),
+                                EnumDesc::Full { value, description } => (value, description),
+                            };
+                            let value = value.value(format!("value of {}", name).as_str())?;
+                            if value > field.max_value() as u64 {
+                                return Err(format!(
+                                    "field {}: value {} of {} wider than the field",
+                                    field.name, value, name
+                                )
+                                .into());
+                            }
+                            field.enum_values.push(EnumValue {
+                                value: value as u16,
+                                name,
+                                description,
+                            });
+                        }
+                        field.enum_values.sort_by_key(|value| value.value);
+                        if let Some(write) = write {
+                            field.write_constraint = Some(write.constraint(&field)?);
+                        }
+                        field
+                    }
+                });
+            }
+            fields.sort_by_key(|field| field.offset);
+            let offset = reg.offset.value(format!("offset of {}", name).as_str())?;
+            registers.push(Register {
+                address: base_address + offset as u32,
+                reset_value: match reg.reset {
+                    Some(reset) => {
+                        let reset = reset.value(format!("reset of {}", name).as_str())?;
+                        u16::try_from(reset).map_err(|_| {
+                            format!(
+                                "register {}: reset 0x{:X} wider than the register",
+                                name, reset
+                            )
+                        })?
+                    }
+                    None => 0,
+                },
+                name,
+                description: reg.description,
+                read_side_effects: reg.read_side_effects,
+                fields,
+                cluster: None,
+            });
+        }
+        registers.sort_by_key(|reg| reg.address);
+        peripherals.push(Peripheral {
+            name,
+            description: periph.description,
+            base_address,
+            registers,
+            clusters: vec![],
+        });
+    }
+    peripherals.sort_by_key(|periph| periph.base_address);
+    Ok(Device {
+        name: desc.name,
+        peripherals,
+    })
+}
+
+/// The device in the YAML form of the format, fields without a description,
+/// access, enumerated values or write constraint written as just their bits.
+pub fn to_yaml(device: &Device) -> String {
+    let mut out = format!("name: {}\nperipherals:\n", key(&device.name));
+    for periph in device.peripherals.iter() {
+        out += format!("  {}:\n", key(&periph.name)).as_str();
+        if let Some(description) = &periph.description {
+            out += format!("    description: {}\n", quoted(description)).as_str();
+        }
+        out += format!("    base_address: 0x{:08X}\n", periph.base_address).as_str();
+        if periph.registers.is_empty() {
+            continue;
+        }
+        out += "    registers:\n";
+        for reg in periph.registers.iter() {
+            out += format!("      {}:\n", key(&reg.name)).as_str();
+            out += format!(
+                "        offset: 0x{:X}\n",
+                reg.address.wrapping_sub(periph.base_address)
+            )
+            .as_str();
+            if let Some(description) = &reg.description {
+                out += format!("        description: {}\n", quoted(description)).as_str();
+            }
+            out += format!("        reset: 0x{:04X}\n", reg.reset_value).as_str();
+            if reg.read_side_effects {
+                out += "        read_side_effects: true\n";
+            }
+            if reg.fields.is_empty() {
+                continue;
+            }
+            out += "        fields:\n";
+            for field in reg.fields.iter() {
+                let bits = if field.width == 1 {
+                    field.offset.to_string()
+                } else {
+                    format!("[{}:{}]", field.offset + field.width - 1, field.offset)
+                };
+                if field.description.is_none()
+                    && field.access == Access::ReadWrite
+                    && field.enum_values.is_empty()
+                    && field.write_constraint.is_none()
+                {
+                    out += format!("          {}: {}\n", key(&field.name), bits).as_str();
+                    continue;
+                }
+                out += format!(
+                    "          {}:\n            bits: {}\n",
+                    key(&field.name),
+                    bits
+                )
+                .as_str();
+                if let Some(description) = &field.description {
+                    out += format!("            description: {}\n", quoted(description)).as_str();
+                }
+                match field.access {
+                    Access::ReadWrite => (),
+                    Access::ReadOnly => out += "            access: read-only\n",
+                    Access::WriteOnly => out += "            access: write-only\n",
+                }
+                match field.write_constraint {
+                    None => (),
+                    Some(WriteConstraint::Range { min, max }) => {
+                        out += format!("            write: [{}, {}]\n", min, max).as_str()
+                    }
+                    Some(WriteConstraint::EnumeratedValues) => {
+                        out += "            write: enumerated\n"
+                    }
+                    Some(WriteConstraint::WriteAsRead) => out += "            write: as-read\n",
+                }
+                if field.enum_values.is_empty() {
+                    continue;
+                }
+                out += "            enum:\n";
+                for val in field.enum_values.iter() {
+                    match &val.description {
+                        Some(description) => {
+                            out += format!(
+                                "              {}: {{ value: {}, description: {} }}\n",
+                                key(&val.name),
+                                val.value,
+                                quoted(description)
+                            )
+                            .as_str()
+                        }
+                        None => {
+                            out += format!("              {}: {}\n", key(&val.name), val.value)
+                                .as_str()
+                        }
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// A name as a mapping key, quoted unless it's an identifier YAML reads as a
+/// string.
+fn key(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !["true", "false", "null", "yes", "no", "on", "off"]
+            .contains(&name.to_ascii_lowercase().as_str());
+    if plain {
+        name.to_string()
+    } else {
+        quoted(name)
+    }
+}
+
+/// Double quoted YAML string on a single line.
+fn quoted(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,212 @@
+//! Interactive console running the [`cli`](crate::cli) commands, with tab
+//! completion of `PERIPH.REG.FIELD` paths and enumerated value names, and
+//! history kept across sessions.
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::cli::{Command, Error, Session};
+use crate::model::{Device, Field};
+
+/// File the command history is persisted to.
+pub const HISTORY_FILE: &str = ".iced_reg_tree_history";
+
+/// Console commands with their arguments and help, the [`Command`]s and the
+/// console's own ones.
+const COMMANDS: [(&str, &str, &str); 7] = [
+    (
+        "read",
+        "PERIPH.REG[.FIELD]",
+        "read and decode a register or a field",
+    ),
+    (
+        "write",
+        "PERIPH.REG[.FIELD]=VALUE",
+        "write an expression, fields by read-modify-write",
+    ),
+    ("dump", "[PERIPH]", "read a peripheral or the whole device"),
+    (
+        "doc",
+        "[md|html] [--values]",
+        "print the register reference, with the current values if set",
+    ),
+    (
+        "convert",
+        "",
+        "print the description in the lightweight YAML format",
+    ),
+    ("help", "", "list the commands"),
+    ("exit", "", "leave the console"),
+];
+
+fn help() -> String {
+    COMMANDS
+        .iter()
+        .map(|(name, args, help)| format!("{:<31}{}", format!("{} {}", name, args), help))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Runs the console until end of input or `exit`.
+pub fn run(session: &Session) -> rustyline::Result<()> {
+    let mut editor = Editor::<Completion, DefaultHistory>::new()?;
+    editor.set_helper(Some(Completion {
+        device: &session.device,
+    }));
+    let _ = editor.load_history(HISTORY_FILE);
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        let mut words = line.split_whitespace().map(str::to_string);
+        let name = words.next().unwrap_or_default();
+        match name.as_str() {
+            "help" => println!("{}", help()),
+            "exit" | "quit" => break,
+            _ => match run_command(session, &name, &mut words) {
+                Ok(output) => print!("{}", output),
+                Err(err) => eprintln!("{}", err),
+            },
+        }
+    }
+    editor.save_history(HISTORY_FILE)
+}
+
+fn run_command(
+    session: &Session,
+    name: &str,
+    words: &mut impl Iterator<Item = String>,
+) -> Result<String, Error> {
+    let command = Command::parse(name, words)?;
+    if let Some(word) = words.next() {
+        return Err(Error::Usage(format!("unexpected argument '{}'", word)));
+    }
+    session.run(&command)
+}
+
+/// Completions of the word ending at `pos` in `line`, as the position the
+/// completed part starts at and the candidates replacing it.
+pub fn complete(device: &Device, line: &str, pos: usize) -> (usize, Vec<String>) {
+    let line = &line[..pos];
+    let word_start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let Some(command) = line[..word_start].split_whitespace().next() else {
+        return (
+            word_start,
+            matching(COMMANDS.map(|(name, _, _)| name), &line[word_start..]),
+        );
+    };
+    let arg_start = line.find(command).unwrap_or(0) + command.len();
+    let arg = &line[arg_start..];
+    match (command, arg.split_once('=')) {
+        ("write", Some((path, _))) => {
+            let start = line
+                .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .map_or(0, |i| i + 1);
+            (
+                start,
+                matching(value_names(device, path.trim()), &line[start..]),
+            )
+        }
+        ("read" | "write" | "dump", None) => {
+            let word = &line[word_start..];
+            let (parents, prefix): (Vec<&str>, &str) = match word.rsplit_once('.') {
+                Some((parents, prefix)) => (parents.split('.').collect(), prefix),
+                None => (vec![], word),
+            };
+            let names: Vec<&str> = match parents.as_slice() {
+                [] => device
+                    .peripherals
+                    .iter()
+                    .map(|periph| periph.name.as_str())
+                    .collect(),
+                [periph] if command != "dump" => device
+                    .peripheral(periph)
+                    .into_iter()
+                    .flat_map(|periph| &periph.registers)
+                    .map(|reg| reg.name.as_str())
+                    .collect(),
+                [periph, reg] if command != "dump" => device
+                    .register(format!("{}.{}", periph, reg).as_str())
+                    .into_iter()
+                    .flat_map(|(_, reg)| &reg.fields)
+                    .map(|field| field.name.as_str())
+                    .collect(),
+                _ => vec![],
+            };
+            (pos - prefix.len(), matching(names, prefix))
+        }
+        _ => (pos, vec![]),
+    }
+}
+
+/// Names an expression written to `path` may use: the enumerated values of a
+/// field, or the fields of a register with all their enumerated values.
+fn value_names<'a>(device: &'a Device, path: &str) -> Vec<&'a str> {
+    let enum_names = |field: &'a Field| field.enum_values.iter().map(|val| val.name.as_str());
+    if let Some((_, reg)) = device.register(path) {
+        return reg
+            .fields
+            .iter()
+            .map(|field| field.name.as_str())
+            .chain(reg.fields.iter().flat_map(enum_names))
+            .collect();
+    }
+    let Some((reg, field)) = path.rsplit_once('.') else {
+        return vec![];
+    };
+    device
+        .register(reg)
+        .and_then(|(_, reg)| reg.field(field))
+        .into_iter()
+        .flat_map(enum_names)
+        .collect()
+}
+
+fn matching<'a>(names: impl IntoIterator<Item = &'a str>, prefix: &str) -> Vec<String> {
+    names
+        .into_iter()
+        .filter(|name| name.starts_with(prefix))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Line editor helper completing against the device model.
+struct Completion<'a> {
+    device: &'a Device,
+}
+
+impl Completer for Completion<'_> {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(self.device, line, pos))
+    }
+}
+
+impl Hinter for Completion<'_> {
+    type Hint = String;
+}
+
+impl Highlighter for Completion<'_> {}
+
+impl Validator for Completion<'_> {}
+
+impl Helper for Completion<'_> {}
@@ -0,0 +1,371 @@
+//! Register sequences scripted in [Rhai](https://rhai.rs).
+//!
+//! Scripts access registers by path and may wait and check results:
+//!
+//! ```text
+//! reg("TIMER0.CR").field("EN").write("Enable");
+//! reg("TIMER0.CR").write("MODE=Single_MATCH | EN");
+//! reg("TIMER0.SR").field("RUN").poll_until("Running", 500);
+//! sleep(10);
+//! assert(reg("TIMER0.CR").field("EN").read() == 1, "timer not enabled");
+//! print(reg("TIMER0.CR").read());
+//! ```
+//!
+//! Values written are numbers or [expressions](crate::expr) over the field and
+//! enumerated value names. A script runs on its own thread and reports what it
+//! prints, reads and writes as [`Event`]s.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use rhai::{Dynamic, Engine, EvalAltResult};
+
+use crate::backend::{self, Backend};
+use crate::bulk_read::Target;
+use crate::expr::{self, Scope};
+use crate::model::{Device, Field, Register};
+
+/// File the script edited in the GUI is persisted to.
+pub const SCRIPT_FILE: &str = "script.rhai";
+
+/// Interval between the reads of `poll_until`.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type RhaiResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Print(String),
+    Read(Target, u16),
+    Written(Target, u16),
+    Finished(Result<(), String>),
+}
+
+/// A running script.
+pub struct Script {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Script {
+    /// Starts running `src`, returning the script and the receiver of its events,
+    /// the last one being [`Event::Finished`].
+    pub fn start(
+        src: String,
+        device: Arc<Device>,
+        backend: Arc<dyn Backend>,
+        config: backend::Config,
+    ) -> (Script, mpsc::UnboundedReceiver<Event>) {
+        let (events, receiver) = mpsc::unbounded();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let context = Arc::new(Context {
+            device,
+            backend,
+            config,
+            events,
+            cancelled: cancelled.clone(),
+        });
+        thread::spawn(move || {
+            let engine = engine(context.clone());
+            let result = engine.run(&src).map_err(|err| match *err {
+                EvalAltResult::ErrorTerminated(..) => "stopped".to_string(),
+                err => err.to_string(),
+            });
+            context.send(Event::Finished(result));
+        });
+        (Script { cancelled }, receiver)
+    }
+
+    /// Stops the script at its next statement, read or wait.
+    pub fn stop(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// What the script functions work with.
+struct Context {
+    device: Arc<Device>,
+    backend: Arc<dyn Backend>,
+    config: backend::Config,
+    events: mpsc::UnboundedSender<Event>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Context {
+    fn send(&self, event: Event) {
+        let _ = self.events.unbounded_send(event);
+    }
+
+    fn check_cancelled(&self) -> RhaiResult<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(EvalAltResult::ErrorTerminated(Dynamic::UNIT, rhai::Position::NONE).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn sleep(&self, duration: Duration) -> RhaiResult<()> {
+        let deadline = Instant::now() + duration;
+        loop {
+            self.check_cancelled()?;
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(());
+            }
+            thread::sleep((deadline - now).min(POLL_INTERVAL));
+        }
+    }
+}
+
+/// A register of the device, returned by `reg(path)`.
+#[derive(Clone)]
+struct Reg {
+    context: Arc<Context>,
+    target: Target,
+}
+
+/// A field of a register, returned by `reg(path).field(name)`.
+#[derive(Clone)]
+struct RegField {
+    reg: Reg,
+    index: usize,
+}
+
+impl Reg {
+    fn find(context: &Arc<Context>, path: &str) -> RhaiResult<Reg> {
+        let (periph, reg) = path
+            .split_once('.')
+            .ok_or_else(|| format!("expected PERIPH.REG, got '{}'", path))?;
+        let unknown = || format!("unknown register '{}'", path);
+        let p = context
+            .device
+            .peripherals
+            .iter()
+            .position(|p| p.name == periph)
+            .ok_or_else(unknown)?;
+        let r = context.device.peripherals[p]
+            .registers
+            .iter()
+            .position(|r| r.name == reg)
+            .ok_or_else(unknown)?;
+        Ok(Reg {
+            context: context.clone(),
+            target: Target {
+                periph: p,
+                reg: r,
+                address: context.device.peripherals[p].registers[r].address,
+            },
+        })
+    }
+
+    fn model(&self) -> &Register {
+        &self.context.device.peripherals[self.target.periph].registers[self.target.reg]
+    }
+
+    fn path(&self) -> String {
+        let periph = &self.context.device.peripherals[self.target.periph];
+        format!("{}.{}", periph.name, self.model().name)
+    }
+
+    fn field(&self, name: &str) -> RhaiResult<RegField> {
+        let index = self
+            .model()
+            .fields
+            .iter()
+            .position(|field| field.name == name)
+            .ok_or_else(|| format!("{} has no field '{}'", self.path(), name))?;
+        Ok(RegField {
+            reg: self.clone(),
+            index,
+        })
+    }
+
+    fn read(&self) -> RhaiResult<u16> {
+        self.context.check_cancelled()?;
+        let value = block_on(backend::read(
+            self.context.backend.clone(),
+            self.context.config,
+            self.target.address,
+        ))
+        .map_err(|err| format!("reading {} failed: {}", self.path(), err))?;
+        self.context.send(Event::Read(self.target, value));
+        Ok(value)
+    }
+
+    fn write(&self, value: u16) -> RhaiResult<()> {
+        self.context.check_cancelled()?;
+        block_on(backend::write(
+            self.context.backend.clone(),
+            self.context.config,
+            self.target.address,
+            value,
+        ))
+        .map_err(|err| format!("writing {} failed: {}", self.path(), err))?;
+        self.context.send(Event::Written(self.target, value));
+        Ok(())
+    }
+
+    fn eval(&self, src: &str) -> RhaiResult<u16> {
+        expr::eval(src, Scope::Register(&self.model().fields))
+            .map_err(|err| format!("{}: {}", self.path(), err).into())
+    }
+}
+
+impl RegField {
+    fn model(&self) -> &Field {
+        &self.reg.model().fields[self.index]
+    }
+
+    fn path(&self) -> String {
+        format!("{}.{}", self.reg.path(), self.model().name)
+    }
+
+    fn read(&self) -> RhaiResult<u16> {
+        Ok(self.model().decode(self.reg.read()?))
+    }
+
+    /// Writes `value` to the field, keeping the other fields as read.
+    fn write(&self, value: u16) -> RhaiResult<()> {
+        let reg = self.reg.read()?;
+        let field = self.model();
+        field
+            .validate(value, field.decode(reg))
+            .map_err(|err| format!("{}: {}", self.path(), err))?;
+        self.reg.write(field.encode(reg, value))
+    }
+
+    fn eval(&self, src: &str) -> RhaiResult<u16> {
+        expr::eval(src, Scope::Field(self.model()))
+            .map_err(|err| format!("{}: {}", self.path(), err).into())
+    }
+}
+
+fn to_u16(value: i64) -> RhaiResult<u16> {
+    u16::try_from(value).map_err(|_| format!("{} doesn't fit in 16 bits", value).into())
+}
+
+fn to_duration(ms: i64) -> Duration {
+    Duration::from_millis(ms.max(0) as u64)
+}
+
+/// Reads with `read` until it returns `value`, failing after `timeout`.
+fn poll_until(
+    context: &Context,
+    path: String,
+    value: u16,
+    timeout: Duration,
+    read: impl Fn() -> RhaiResult<u16>,
+) -> RhaiResult<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let read = read()?;
+        if read == value {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(format!(
+                "{} is {}, not {} after {} ms",
+                path,
+                read,
+                value,
+                timeout.as_millis()
+            )
+            .into());
+        }
+        context.sleep(POLL_INTERVAL)?;
+    }
+}
+
+fn engine(context: Arc<Context>) -> Engine {
+    let mut engine = Engine::new();
+
+    let ctx = context.clone();
+    engine.on_print(move |s| ctx.send(Event::Print(s.to_string())));
+    let ctx = context.clone();
+    engine.on_debug(move |s, _, _| ctx.send(Event::Print(s.to_string())));
+    let ctx = context.clone();
+    engine.on_progress(move |_| {
+        ctx.cancelled
+            .load(Ordering::Relaxed)
+            .then_some(Dynamic::UNIT)
+    });
+
+    let ctx = context.clone();
+    engine.register_fn("reg", move |path: &str| Reg::find(&ctx, path));
+    let ctx = context.clone();
+    engine.register_fn("sleep", move |ms: i64| ctx.sleep(to_duration(ms)));
+    engine.register_fn("assert", |cond: bool| -> RhaiResult<()> {
+        if cond {
+            Ok(())
+        } else {
+            Err("assertion failed".into())
+        }
+    });
+    engine.register_fn("assert", |cond: bool, msg: &str| -> RhaiResult<()> {
+        if cond {
+            Ok(())
+        } else {
+            Err(format!("assertion failed: {}", msg).into())
+        }
+    });
+
+    engine
+        .register_type_with_name::<Reg>("Reg")
+        .register_fn("field", |reg: &mut Reg, name: &str| reg.field(name))
+        .register_fn("read", |reg: &mut Reg| reg.read().map(i64::from))
+        .register_fn("write", |reg: &mut Reg, value: i64| {
+            reg.write(to_u16(value)?)
+        })
+        .register_fn("write", |reg: &mut Reg, src: &str| {
+            reg.write(reg.eval(src)?)
+        })
+        .register_fn("poll_until", |reg: &mut Reg, value: i64, ms: i64| {
+            poll_until(
+                &reg.context,
+                reg.path(),
+                to_u16(value)?,
+                to_duration(ms),
+                || reg.read(),
+            )
+        })
+        .register_fn("poll_until", |reg: &mut Reg, src: &str, ms: i64| {
+            let value = reg.eval(src)?;
+            poll_until(&reg.context, reg.path(), value, to_duration(ms), || {
+                reg.read()
+            })
+        });
+
+    engine
+        .register_type_with_name::<RegField>("Field")
+        .register_fn("read", |field: &mut RegField| field.read().map(i64::from))
+        .register_fn("write", |field: &mut RegField, value: i64| {
+            field.write(to_u16(value)?)
+        })
+        .register_fn("write", |field: &mut RegField, src: &str| {
+            field.write(field.eval(src)?)
+        })
+        .register_fn("poll_until", |field: &mut RegField, value: i64, ms: i64| {
+            poll_until(
+                &field.reg.context,
+                field.path(),
+                to_u16(value)?,
+                to_duration(ms),
+                || field.read(),
+            )
+        })
+        .register_fn("poll_until", |field: &mut RegField, src: &str, ms: i64| {
+            let value = field.eval(src)?;
+            poll_until(
+                &field.reg.context,
+                field.path(),
+                value,
+                to_duration(ms),
+                || field.read(),
+            )
+        });
+
+    engine
+}
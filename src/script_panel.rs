@@ -0,0 +1,114 @@
+//! Editor of a [`script`](crate::script) with a console showing its output.
+//!
+//! The panel doesn't access the device itself: running is handed to the
+//! application as [`Action::Run`], which starts the [`Script`], keeps it in
+//! [`ScriptPanel::running`] and forwards its events as [`Message::Event`].
+
+use iced::widget::{button, column, row, scrollable, text, text_editor, Column};
+use iced::{Element, Renderer, Theme};
+
+use crate::reg_tree;
+use crate::script::{Event, Script};
+
+pub struct ScriptPanel {
+    pub content: text_editor::Content,
+    pub console: Vec<String>,
+    pub running: Option<Script>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Edit(text_editor::Action),
+    Run,
+    Stop,
+    ClearConsole,
+    Event(Event),
+}
+
+pub enum Action {
+    None,
+    /// Start the script and forward its events.
+    Run(String),
+    /// Show a register access of the script in the tree.
+    Tree(reg_tree::Message),
+}
+
+impl ScriptPanel {
+    pub fn new(src: &str) -> Self {
+        ScriptPanel {
+            content: text_editor::Content::with_text(src),
+            console: vec![],
+            running: None,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::Edit(action) => {
+                self.content.perform(action);
+                Action::None
+            }
+            Message::Run => {
+                if self.running.is_some() {
+                    return Action::None;
+                }
+                self.console.clear();
+                Action::Run(self.content.text())
+            }
+            Message::Stop => {
+                if let Some(script) = &self.running {
+                    script.stop();
+                }
+                Action::None
+            }
+            Message::ClearConsole => {
+                self.console.clear();
+                Action::None
+            }
+            Message::Event(Event::Print(line)) => {
+                self.console.push(line);
+                Action::None
+            }
+            Message::Event(Event::Read(target, value)) => {
+                Action::Tree(reg_tree::Message::ReadDone(target, Ok(value)))
+            }
+            Message::Event(Event::Written(target, value)) => {
+                Action::Tree(reg_tree::Message::Written(target, value))
+            }
+            Message::Event(Event::Finished(result)) => {
+                self.running = None;
+                self.console.push(match result {
+                    Ok(()) => "Script finished".to_string(),
+                    Err(err) => format!("Script failed: {}", err),
+                });
+                Action::None
+            }
+        }
+    }
+
+    pub fn view(&self) -> Element<'_, Message, Theme, Renderer> {
+        let buttons = row![
+            button(text("Run"))
+                .padding([0, 5])
+                .on_press_maybe(self.running.is_none().then_some(Message::Run)),
+            button(text("Stop"))
+                .padding([0, 5])
+                .on_press_maybe(self.running.is_some().then_some(Message::Stop)),
+            button(text("Clear"))
+                .padding([0, 5])
+                .on_press(Message::ClearConsole),
+        ]
+        .spacing(10);
+        let console =
+            Column::with_children(self.console.iter().map(|line| text(line.as_str()).into()));
+        column![
+            text_editor(&self.content)
+                .on_action(Message::Edit)
+                .height(200),
+            buttons,
+            scrollable(console).height(100),
+        ]
+        .spacing(10)
+        .into()
+    }
+}